@@ -0,0 +1,199 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use rusqlite::backup::Progress;
+use rusqlite::{Connection, DatabaseName};
+use sha2::{Digest, Sha256};
+use crate::database::EntryDB;
+
+const MANIFEST: &str = "manifest.txt";
+const BACKUP_PREFIX: &str = "journal-";
+const BACKUP_EXTENSION: &str = ".tar.zst";
+
+fn sha256_file(path: &Path) -> String {
+    let data = fs::read(path).expect("Could not read file for checksum");
+    Sha256::digest(&data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn backup_dir(db: &EntryDB) -> PathBuf {
+    Path::new(&db.config.main_dir).join("backups")
+}
+
+fn timestamp() -> String {
+    chrono::offset::Local::now().format("%Y%m%d-%H%M%S").to_string()
+}
+
+/// Writes a consistent copy of the database and every entry into a zstd compressed tar archive.
+/// The archive holds a manifest with the SHA-256 checksum of every file so it can be verified on restore.
+pub fn create_backup(db: &EntryDB, out: Option<&str>) -> String {
+    let dir = backup_dir(db);
+    fs::create_dir_all(&dir).expect("Could not create backup directory");
+
+    let stamp = timestamp();
+    let out = match out {
+        Some(out) => PathBuf::from(out),
+        None => {
+            // Never overwrite a backup taken within the same second, e.g. the one being restored
+            let mut out = dir.join(format!("{}{}{}", BACKUP_PREFIX, stamp, BACKUP_EXTENSION));
+            let mut n = 1;
+            while out.exists() {
+                out = dir.join(format!("{}{}-{}{}", BACKUP_PREFIX, stamp, n, BACKUP_EXTENSION));
+                n += 1;
+            }
+            out
+        },
+    };
+
+    // The online backup API copies the database without blocking or racing other connections
+    let db_copy = dir.join(format!(".db-{}.sqlite", stamp));
+    db.conn.backup(DatabaseName::Main, &db_copy, None).expect("Could not back up database");
+
+    let mut files = vec![(db_copy.clone(), "db.sqlite".to_string())];
+    let mut names = db.config.get_files();
+    names.sort();
+    for name in names {
+        files.push((PathBuf::from(db.config.get_entry_path(&name)), format!("entries/{}", name)));
+    }
+
+    let mut manifest = format!("created: {}\n", chrono::offset::Local::now().to_rfc3339());
+    for (path, archive_path) in files.iter() {
+        manifest.push_str(&format!("{}  {}\n", sha256_file(path), archive_path));
+    }
+
+    let file = fs::File::create(&out).expect("Could not create backup archive");
+    let encoder = zstd::Encoder::new(file, 0).expect("Could not start compression").auto_finish();
+    let mut archive = tar::Builder::new(encoder);
+
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(chrono::offset::Utc::now().timestamp() as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, MANIFEST, manifest.as_bytes()).expect("Could not write manifest");
+
+    for (path, archive_path) in files.iter() {
+        archive.append_path_with_name(path, archive_path).expect("Could not add file to backup");
+    }
+    archive.finish().expect("Could not write backup archive");
+    drop(archive);
+
+    fs::remove_file(db_copy).expect("Could not remove temporary database copy");
+    out.to_string_lossy().to_string()
+}
+
+/// Deletes the oldest backups in the backup directory, keeping the `keep` most recent
+pub fn rotate_backups(db: &EntryDB, keep: usize) -> Vec<String> {
+    let mut backups = fs::read_dir(backup_dir(db))
+        .map(|items| items
+            .filter_map(|i| i.ok())
+            .map(|i| (i.metadata().and_then(|m| m.modified()).ok(), i.file_name().to_string_lossy().to_string()))
+            .filter(|(_, name)| name.starts_with(BACKUP_PREFIX) && name.ends_with(BACKUP_EXTENSION))
+            .collect::<Vec<_>>())
+        .unwrap_or_default();
+    backups.sort();
+
+    let count = backups.len().saturating_sub(keep);
+    backups.into_iter().take(count).map(|(_, name)| {
+        fs::remove_file(backup_dir(db).join(&name)).expect("Could not remove old backup");
+        name
+    }).collect()
+}
+
+/// Every file below `dir`, relative to `root` and written with `/` like the manifest's paths
+fn extracted_files(root: &Path, dir: &Path, files: &mut Vec<String>) {
+    for item in fs::read_dir(dir).into_iter().flatten().filter_map(|i| i.ok()) {
+        let path = item.path();
+        if item.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+            extracted_files(root, &path, files);
+        } else {
+            let relative = path.strip_prefix(root).unwrap_or(&path)
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<String>>();
+            files.push(relative.join("/"));
+        }
+    }
+}
+
+/// Checks every file listed in an extracted backup's manifest and that nothing else was extracted,
+/// returning the problems found
+fn verify(staging: &Path) -> Vec<String> {
+    let manifest = match fs::read_to_string(staging.join(MANIFEST)) {
+        Ok(manifest) => manifest,
+        Err(_) => return vec!["Backup has no manifest".to_string()],
+    };
+
+    let mut problems = Vec::new();
+    let mut listed = vec![MANIFEST];
+    for line in manifest.lines().skip(1) {
+        let (checksum, path) = match line.split_once("  ") {
+            Some(parts) => parts,
+            None => {
+                problems.push(format!("Malformed manifest line: {}", line));
+                continue;
+            }
+        };
+        listed.push(path);
+
+        let file = staging.join(path);
+        if !file.is_file() {
+            problems.push(format!("Missing {}", path));
+        } else if sha256_file(&file) != checksum {
+            problems.push(format!("Checksum mismatch for {}", path));
+        }
+    }
+    if !listed.contains(&"db.sqlite") {
+        problems.push("Backup has no database".to_string());
+    }
+
+    // Anything the manifest doesn't list would be restored unchecked
+    let mut extracted = Vec::new();
+    extracted_files(staging, staging, &mut extracted);
+    extracted.sort();
+    for path in extracted {
+        if !listed.contains(&path.as_str()) {
+            problems.push(format!("{} is not in the manifest", path));
+        }
+    }
+    problems
+}
+
+/// Restores the journal from a backup archive. The archive is extracted and verified first,
+/// and the current journal is backed up before anything is overwritten.
+pub fn restore_backup(db: &EntryDB, archive_path: &str) -> Result<String, Vec<String>> {
+    let staging = Path::new(&db.config.main_dir).join(format!(".restore-{}", timestamp()));
+    fs::create_dir_all(&staging).expect("Could not create restore directory");
+
+    let file = fs::File::open(archive_path).expect("Could not open backup archive");
+    let decoder = zstd::Decoder::new(file).expect("Could not read backup archive");
+    let unpacked = tar::Archive::new(decoder).unpack(&staging);
+
+    let problems = match unpacked {
+        Ok(_) => verify(&staging),
+        Err(e) => vec![format!("Could not extract backup: {}", e)],
+    };
+    if !problems.is_empty() {
+        fs::remove_dir_all(&staging).expect("Could not clean up restore directory");
+        return Err(problems);
+    }
+
+    let safety_backup = create_backup(db, None);
+
+    // Restore through the backup API too, so the open connection sees the restored pages
+    let mut conn = Connection::open(&db.config.db).expect("Could not open Database");
+    conn.restore(DatabaseName::Main, staging.join("db.sqlite"), None::<fn(Progress)>)
+        .expect("Could not restore database");
+
+    let entry_dir = Path::new(&db.config.entry_dir);
+    if entry_dir.exists() {
+        fs::remove_dir_all(entry_dir).expect("Could not remove current entries");
+    }
+    let staged_entries = staging.join("entries");
+    if staged_entries.exists() {
+        fs::rename(staged_entries, entry_dir).expect("Could not restore entries");
+    } else {
+        fs::create_dir_all(entry_dir).expect("Could not create entries directory");
+    }
+
+    fs::remove_dir_all(&staging).expect("Could not clean up restore directory");
+    Ok(safety_backup)
+}
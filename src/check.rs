@@ -0,0 +1,204 @@
+use std::fmt;
+use std::fs;
+use crate::database::{EntryDB, ENTRY_REFERENCES};
+use crate::file_operations::{extract_entry_number, file_exists, from_db_date, Entry};
+
+/// Classes `--repair` can fix. Number gaps (`gaps`) are only reported.
+pub const PROBLEM_CLASSES: [&str; 5] = ["missing-files", "untracked-files", "numbers", "dates", "orphans"];
+
+#[derive(Debug)]
+pub enum Problem {
+    /// A row in `entries` whose file can't be read
+    MissingFile(String),
+    /// A file in the entry directory without a row
+    UntrackedFile(String),
+    DuplicateNumber(u32),
+    /// Numbers should run from 1 without gaps. Only reported, entries are never renumbered.
+    NumberGap(u32),
+    BadDate { entry: String, column: String, value: String },
+    OrphanRow { table: String, entry: String },
+    OrphanTag { entry: String, tag: String },
+}
+
+impl Problem {
+    pub fn class(&self) -> &'static str {
+        match self {
+            Problem::MissingFile(_) => "missing-files",
+            Problem::UntrackedFile(_) => "untracked-files",
+            Problem::DuplicateNumber(_) => "numbers",
+            Problem::NumberGap(_) => "gaps",
+            Problem::BadDate { .. } => "dates",
+            Problem::OrphanRow { .. } | Problem::OrphanTag { .. } => "orphans",
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::MissingFile(name) => write!(f, "{} has no readable file", name),
+            Problem::UntrackedFile(name) => write!(f, "{} is not in the database", name),
+            Problem::DuplicateNumber(number) => write!(f, "number {} is used by several entries", number),
+            Problem::NumberGap(number) => write!(f, "number {} is missing", number),
+            Problem::BadDate { entry, column, value } => write!(f, "{} has an unreadable {}: {:?}", entry, column, value),
+            Problem::OrphanRow { table, entry } => write!(f, "{} references missing entry {}", table, entry),
+            Problem::OrphanTag { entry, tag } => write!(f, "{} is tagged with missing tag {}", entry, tag),
+        }
+    }
+}
+
+fn query_strings<P: rusqlite::Params>(db: &EntryDB, query: &str, params: P) -> Vec<Vec<Option<String>>> {
+    let mut stmt = db.conn.prepare(query).expect("Could not prepare check query");
+    let columns = stmt.column_count();
+    let rows = stmt.query_map(params, |row| {
+        Ok((0..columns).map(|i| row.get::<_, Option<String>>(i).ok().flatten()).collect())
+    }).expect("Error running check query");
+
+    rows.filter_map(|r| r.ok()).collect()
+}
+
+fn check_files(db: &EntryDB, problems: &mut Vec<Problem>) {
+    let names = query_strings(db, "SELECT name FROM entries ORDER BY name", [])
+        .into_iter()
+        .filter_map(|row| row[0].clone())
+        .collect::<Vec<String>>();
+
+    for name in names.iter() {
+        if fs::File::open(db.config.get_entry_path(name)).is_err() {
+            problems.push(Problem::MissingFile(name.clone()));
+        }
+    }
+
+    let mut files = db.config.get_files();
+    files.sort();
+    for file in files {
+        if !names.contains(&file) {
+            problems.push(Problem::UntrackedFile(file));
+        }
+    }
+}
+
+fn check_numbers(db: &EntryDB, problems: &mut Vec<Problem>) {
+    let rows = query_strings(db, "SELECT CAST(number AS TEXT), CAST(COUNT(*) AS TEXT) FROM entries WHERE number IS NOT NULL GROUP BY number ORDER BY number", []);
+    let counts = rows.iter()
+        .filter_map(|row| Some((row[0].as_ref()?.parse::<u32>().ok()?, row[1].as_ref()?.parse::<u32>().ok()?)))
+        .collect::<Vec<(u32, u32)>>();
+
+    for (number, count) in counts.iter() {
+        if *count > 1 {
+            problems.push(Problem::DuplicateNumber(*number));
+        }
+    }
+
+    let largest = counts.last().map(|(number, _)| *number).unwrap_or(0);
+    for number in 1..largest {
+        if !counts.iter().any(|(n, _)| *n == number) {
+            problems.push(Problem::NumberGap(number));
+        }
+    }
+}
+
+fn check_dates(db: &EntryDB, problems: &mut Vec<Problem>) {
+    for row in query_strings(db, "SELECT name, entry_date, access_date FROM entries ORDER BY name", []) {
+        let entry = row[0].clone().unwrap_or_default();
+        for (column, value) in [("entry_date", &row[1]), ("access_date", &row[2])] {
+            let value = value.clone().unwrap_or_default();
+            if !value.trim().is_empty() && from_db_date(&value, None).is_none() {
+                problems.push(Problem::BadDate { entry: entry.clone(), column: column.to_string(), value });
+            }
+        }
+    }
+}
+
+fn check_orphans(db: &EntryDB, problems: &mut Vec<Problem>) {
+    for (table, column) in ENTRY_REFERENCES {
+        let query = format!(
+            "SELECT DISTINCT {column} FROM {table} WHERE {column} NOT IN (SELECT name FROM entries) ORDER BY {column}",
+            column = column, table = table);
+        for row in query_strings(db, &query, []) {
+            problems.push(Problem::OrphanRow { table: table.to_string(), entry: row[0].clone().unwrap_or_default() });
+        }
+    }
+
+    let query = "SELECT entry, tag FROM entry_tags WHERE tag NOT IN (SELECT name FROM tags) ORDER BY entry, tag";
+    for row in query_strings(db, query, []) {
+        problems.push(Problem::OrphanTag {
+            entry: row[0].clone().unwrap_or_default(),
+            tag: row[1].clone().unwrap_or_default(),
+        });
+    }
+}
+
+/// Looks for inconsistencies between the database and the entry directory
+pub fn check(db: &EntryDB) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    check_files(db, &mut problems);
+    check_numbers(db, &mut problems);
+    check_dates(db, &mut problems);
+    check_orphans(db, &mut problems);
+    problems
+}
+
+/// Leaves a duplicated number on one entry, preferring the one whose file name carries it,
+/// and clears it from the others. Files are never renamed, so numbers and names stay in step.
+fn deduplicate_number(db: &EntryDB, number: u32) {
+    let mut names = query_strings(db, "SELECT name FROM entries WHERE number = ?1 ORDER BY name", (number,))
+        .into_iter()
+        .filter_map(|row| row[0].clone())
+        .collect::<Vec<String>>();
+    if let Some(i) = names.iter().position(|name| extract_entry_number(name) == Some(number)) {
+        names.remove(i);
+    } else if !names.is_empty() {
+        names.remove(0);
+    }
+
+    for name in names {
+        db.conn.execute("UPDATE entries SET number = NULL WHERE name = ?1", (&name,))
+            .expect("Could not clear duplicate number");
+    }
+}
+
+/// Fixes the problems belonging to `classes`, returning how many were repaired
+pub fn repair(db: &EntryDB, problems: &[Problem], classes: &[&str]) -> usize {
+    let mut repaired = 0;
+
+    for problem in problems.iter().filter(|p| classes.contains(&p.class())) {
+        match problem {
+            Problem::MissingFile(name) => {
+                db.conn.execute("DELETE FROM entries WHERE name = ?1", (name,)).expect("Could not remove entry");
+            },
+            Problem::UntrackedFile(name) => {
+                db.add_untracked_file(name);
+            },
+            Problem::DuplicateNumber(number) => deduplicate_number(db, *number),
+            // Closing a gap would mean renumbering entries, which is left to `journal meta`
+            Problem::NumberGap(_) => continue,
+            Problem::BadDate { entry, column, .. } => {
+                // Fall back to the date in the file's header for entry dates, and forget bad access dates
+                let from_file = column == "entry_date" && file_exists(&db.config.get_entry_path(entry));
+                let (date, offset) = match from_file {
+                    true => {
+                        let restored = Entry::from_file(&db.config.entry_dir, entry);
+                        (restored.entry_string(), restored.entry_offset())
+                    },
+                    false => (None, None),
+                };
+                let offset_column = column.replace("_date", "_offset");
+                db.conn.execute(
+                    &format!("UPDATE entries SET {} = ?1, {} = ?2 WHERE name = ?3", column, offset_column),
+                    (date, offset, entry))
+                    .expect("Could not repair date");
+            },
+            Problem::OrphanRow { table, entry } => {
+                let column = ENTRY_REFERENCES.iter().find(|(t, _)| t == table).map(|(_, c)| *c).unwrap_or("entry");
+                db.conn.execute(&format!("DELETE FROM {} WHERE {} = ?1", table, column), (entry,))
+                    .expect("Could not remove orphaned row");
+            },
+            Problem::OrphanTag { tag, .. } => {
+                db.add_tag(tag).ok();
+            },
+        }
+        repaired += 1;
+    }
+    repaired
+}
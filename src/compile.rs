@@ -0,0 +1,75 @@
+use std::fs;
+use std::path::Path;
+use crate::database::EntryDB;
+use crate::file_operations::{read_entry_body, Entry};
+use crate::format::{convert_headings, Format};
+
+/// Stitches the entries of a project into a single manuscript in `format`, one chapter per entry.
+/// Headings inside the entries are converted to `format` and nested under their chapter.
+pub fn build_manuscript(project: &str, entries: &[Entry], format: Format) -> String {
+    let mut manuscript = match format {
+        Format::Org => format!("#+TITLE: {}\n\n", project),
+        _ => format!("{}\n", format.heading(1, project)),
+    };
+
+    for (i, entry) in entries.iter().enumerate() {
+        let heading = format!("Chapter {}: {}", i + 1, entry.display_title());
+        // Org has no document heading, so its chapters start at the top level
+        let level = if format == Format::Org { 1 } else { 2 };
+        manuscript.push_str(&format!("{}\n", format.heading(level, &heading)));
+
+        let body = read_entry_body(&entry.path);
+        manuscript.push_str(convert_headings(body.trim(), Format::from_name(&entry.name), format, level).trim());
+        manuscript.push_str("\n\n");
+    }
+    manuscript
+}
+
+/// Compiles a project into `output`, defaulting to `<project>.md` in the journal directory,
+/// or `<project>.org` for journals writing Org.
+/// The output format is picked from the file extension.
+pub fn compile_project(db: &EntryDB, project: &str, output: Option<&str>) -> Option<String> {
+    let entries = db.get_project_entries(project);
+    if entries.is_empty() {
+        return None;
+    }
+
+    let output = match output {
+        Some(path) => path.to_string(),
+        None => Path::new(&db.config.main_dir)
+            .join(format!("{}.{}", project, match db.default_format() {
+                Format::Org => "org",
+                _ => "md",
+            }))
+            .to_string_lossy()
+            .to_string(),
+    };
+
+    let manuscript = build_manuscript(project, &entries, Format::from_name(&output));
+    fs::write(&output, manuscript).expect("Could not write manuscript");
+    Some(output)
+}
+
+/// Joins entries into one document in `format`, each under a heading with its title.
+/// Unlike a manuscript there is no document title and no chapter numbering.
+pub fn build_export(entries: &[Entry], format: Format) -> String {
+    let mut export = String::new();
+    for entry in entries {
+        export.push_str(&format!("{}\n", format.heading(1, &entry.display_title())));
+        let body = read_entry_body(&entry.path);
+        export.push_str(convert_headings(body.trim(), Format::from_name(&entry.name), format, 1).trim());
+        export.push_str("\n\n");
+    }
+    export
+}
+
+/// Writes the chosen entries to `output` as one document, in the format of its extension.
+/// With `chapters` it is laid out as a manuscript, otherwise as a plain export.
+pub fn export_entries(entries: &[Entry], output: &str, chapters: bool) {
+    let format = Format::from_name(output);
+    let document = match chapters {
+        true => build_manuscript("Journal Export", entries, format),
+        false => build_export(entries, format),
+    };
+    fs::write(output, document).expect("Could not write export");
+}
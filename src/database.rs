@@ -1,7 +1,90 @@
 use rusqlite::{Connection, Result};
 use std::path::Path;
-use std::fs;
-use crate::file_operations::{sort_entries_by_number, Entry};
+use std::time::Duration;
+use std::{fmt, fs, io};
+use crate::file_operations::{content_title, from_db_date, modified_date, read_entry_body, strip_header, rewrite_entry_date, sort_entries_by_number, to_db_date, Entry};
+use chrono::{DateTime, FixedOffset};
+use crate::mentions::{extract_hashtags, extract_mentions};
+use crate::links::{extract_links, link_matches, rewrite_links};
+use crate::git;
+use crate::lock;
+use crate::names::{validate_name, NameError};
+use crate::format::Format;
+use crate::front_matter::FrontMatter;
+use serde_json::{json, Value};
+
+/// Number of operations kept in the undo log
+const UNDO_HISTORY: u32 = 200;
+
+/// Number of opens kept in the access log
+const ACCESS_HISTORY: u32 = 1000;
+
+/// Tables holding a reference to an entry, with the column naming it
+pub const ENTRY_REFERENCES: [(&str, &str); 5] = [
+    ("entry_tags", "entry"),
+    ("entry_people", "entry"),
+    ("project_entries", "entry"),
+    ("links", "source"),
+    ("access_log", "entry"),
+];
+
+#[derive(Debug)]
+pub enum EntryError {
+    NameTaken(String),
+    NumberTaken(u32),
+    /// Open in the editor of another session
+    Locked(String),
+    InvalidName(NameError),
+    Io(io::Error),
+    Db(rusqlite::Error),
+}
+
+impl fmt::Display for EntryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EntryError::NameTaken(name) => write!(f, "An entry named {} already exists", name),
+            EntryError::NumberTaken(number) => write!(f, "Entry number {} is already used", number),
+            EntryError::Locked(name) => write!(f, "{} is being edited in another session", name),
+            EntryError::InvalidName(e) => write!(f, "{}", e),
+            EntryError::Io(e) => write!(f, "File error: {}", e),
+            EntryError::Db(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<NameError> for EntryError {
+    fn from(e: NameError) -> Self {
+        EntryError::InvalidName(e)
+    }
+}
+
+impl From<io::Error> for EntryError {
+    fn from(e: io::Error) -> Self {
+        EntryError::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for EntryError {
+    fn from(e: rusqlite::Error) -> Self {
+        EntryError::Db(e)
+    }
+}
+
+/// Which of an entry's dates to sort or filter by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateField {
+    Created,
+    Accessed,
+}
+
+impl DateField {
+    pub fn column(&self) -> &'static str {
+        match self {
+            DateField::Created => "entry_date",
+            DateField::Accessed => "access_date",
+        }
+    }
+}
 
 pub struct PathConfig {
     pub db: String,
@@ -44,9 +127,157 @@ pub struct EntryDB {
 
 impl EntryDB {
     pub fn new(config: PathConfig) -> Self {
-        Self {
+        let db = Self {
             conn: Connection::open(&config.db).expect("Could not open Database"),
             config
+        };
+        // WAL lets other sessions read while one writes, and the timeout makes writers wait for each other instead of failing
+        db.conn.busy_timeout(Duration::from_secs(10)).expect("Could not set busy timeout");
+        db.conn.pragma_update(None, "journal_mode", "WAL").expect("Could not enable WAL mode");
+        db.init_tables();
+        db.migrate();
+        db.conn.execute_batch("PRAGMA foreign_keys = ON").expect("Could not enable foreign keys");
+        db
+    }
+
+    /// Rebuilds a reference table with the current schema, copying over `columns` from the rows
+    /// that still point at an entry. Returns the number of orphaned rows dropped.
+    fn rebuild_reference_table(&self, table: &str, columns: &str, entry_column: &str) -> usize {
+        let old = format!("{}_old", table);
+        let leftover: bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)", (&old,), |row| row.get(0))
+            .expect("Could not look for old table");
+        if leftover {
+            // An upgrade interrupted before migrations ran in a transaction left the rows in the old table,
+            // and the table in its place is the empty one init_tables made
+            self.conn.execute_batch(&format!("DROP TABLE {}", table))
+        } else {
+            self.conn.execute_batch(&format!("ALTER TABLE {} RENAME TO {}", table, old))
+        }.expect("Could not migrate table");
+        self.init_tables();
+
+        let before: usize = self.conn.query_row(&format!("SELECT COUNT(*) FROM {}", old), [], |row| row.get(0))
+            .expect("Could not count rows");
+        let copied = self.conn.execute(&format!(
+            "INSERT OR IGNORE INTO {table} ({columns}) SELECT {columns} FROM {old} WHERE {entry_column} IN (SELECT name FROM entries)",
+            table = table, columns = columns, old = old, entry_column = entry_column), ())
+            .expect("Could not copy rows into migrated table");
+        self.conn.execute_batch(&format!("DROP TABLE {}", old)).expect("Could not drop old table");
+        before - copied
+    }
+
+    /// Runs one schema upgrade and records `version` in the same transaction,
+    /// so an interrupted upgrade leaves the database as it was and is redone on the next start
+    fn migrate_to(&self, version: u32, upgrade: impl FnOnce()) {
+        let tx = self.conn.unchecked_transaction().expect("Could not start migration");
+        upgrade();
+        self.conn.execute_batch(&format!("PRAGMA user_version = {}", version))
+            .expect("Could not update schema version");
+        tx.commit().expect("Could not commit migration");
+    }
+
+    /// One time schema upgrades, tracked with SQLite's user_version
+    fn migrate(&self) {
+        let version: u32 = self.conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+            .expect("Could not read schema version");
+
+        if version < 1 {
+            self.migrate_to(1, || {
+                // Version 1 adds ON UPDATE CASCADE to the reference tables and drops their orphaned rows.
+                // Missing tags, people and projects are recreated rather than losing the references.
+                self.conn.execute_batch("
+                    INSERT OR IGNORE INTO tags (name) SELECT DISTINCT tag FROM entry_tags;
+                    INSERT OR IGNORE INTO people (name) SELECT DISTINCT person FROM entry_people;
+                    INSERT OR IGNORE INTO projects (name) SELECT DISTINCT project FROM project_entries;
+                ").expect("Could not restore referenced rows");
+
+                let removed = self.rebuild_reference_table("entry_tags", "tag, entry", "entry")
+                    + self.rebuild_reference_table("entry_people", "person, entry", "entry")
+                    + self.rebuild_reference_table("project_entries", "project, entry, position", "entry")
+                    + self.rebuild_reference_table("links", "source, target", "source");
+                if removed > 0 {
+                    println!("Removed {} rows referencing deleted entries", removed);
+                }
+            });
+        }
+
+        if version < 2 {
+            self.migrate_to(2, || {
+                // Version 2 moves dates from RFC 2822 strings to ISO-8601 in UTC plus the original offset
+                let mut stmt = self.conn.prepare("SELECT name, entry_date, access_date FROM entries")
+                    .expect("Could not select entries in DB");
+                let rows = stmt.query_map([], |row| Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))).expect("Error reading entries")
+                    .filter_map(|r| r.ok())
+                    .collect::<Vec<_>>();
+
+                // Dates that don't parse are left as they are, for `journal check` to report
+                let mut unreadable = 0;
+                let mut parse = |value: Option<String>| {
+                    let value = value.filter(|v| !v.trim().is_empty())?;
+                    let date = DateTime::parse_from_rfc2822(value.trim()).ok();
+                    if date.is_none() && from_db_date(&value, None).is_none() {
+                        unreadable += 1;
+                    }
+                    date
+                };
+                for (name, entry_date, access_date) in rows {
+                    let entry_date = parse(entry_date);
+                    let access_date = parse(access_date);
+                    self.conn.execute(
+                        "UPDATE entries SET entry_date = COALESCE(?1, entry_date), entry_offset = COALESCE(?2, entry_offset),
+                            access_date = COALESCE(?3, access_date), access_offset = COALESCE(?4, access_offset) WHERE name = ?5",
+                        (
+                            entry_date.as_ref().map(to_db_date),
+                            entry_date.map(|d| d.offset().local_minus_utc()),
+                            access_date.as_ref().map(to_db_date),
+                            access_date.map(|d| d.offset().local_minus_utc()),
+                            &name,
+                        )).expect("Could not migrate entry dates");
+                }
+                if unreadable > 0 {
+                    println!("{} dates could not be read and were left as they are, run journal check to fix them", unreadable);
+                }
+            });
+        }
+
+        if version < 3 {
+            self.migrate_to(3, || {
+                // Version 3 takes titles from the entry's first line when the front matter has none.
+                // Older versions wrote the file name based title into every new entry's front matter, so that one is dropped.
+                for mut entry in self.get_entries() {
+                    if let Ok(content) = fs::read_to_string(&entry.path) {
+                        let legacy = FrontMatter::parse(&content).0
+                            .and_then(|m| m.title)
+                            .is_some_and(|title| title == entry.default_title());
+                        if legacy {
+                            fs::write(&entry.path, FrontMatter::set_value(&content, "title", None, Format::from_name(&entry.name))).ok();
+                        }
+                    }
+                    self.index_title(&mut entry);
+                }
+            });
+        }
+
+        if version < 4 {
+            self.migrate_to(4, || {
+                // Version 4 starts the access log with the last time each entry was opened
+                self.conn.execute(
+                    "INSERT INTO access_log (entry, date, offset) SELECT name, access_date, access_offset FROM entries WHERE access_date IS NOT NULL",
+                    ()).expect("Could not seed access log");
+            });
+        }
+
+        if version < 5 {
+            self.migrate_to(5, || {
+                // Version 5 keeps a lifetime open count on each entry, starting from the opens still in the access log
+                self.conn.execute(
+                    "UPDATE entries SET open_count = (SELECT COUNT(*) FROM access_log WHERE entry = entries.name)",
+                    ()).expect("Could not seed open counts");
+            });
         }
     }
 
@@ -56,9 +287,31 @@ impl EntryDB {
                 number INTEGER UNIQUE,
                 name TEXT PRIMARY KEY NOT NULL,
                 entry_date TEXT,
-                access_date TEXT
+                access_date TEXT,
+                title TEXT,
+                status TEXT,
+                entry_offset INTEGER,
+                access_offset INTEGER,
+                modified_date TEXT,
+                modified_offset INTEGER,
+                pinned INTEGER NOT NULL DEFAULT 0,
+                open_count INTEGER NOT NULL DEFAULT 0
             )
         ", ()).expect("Could not add table");
+
+        self.add_column("entries", "title", "TEXT");
+        self.add_column("entries", "status", "TEXT");
+        self.add_column("entries", "entry_offset", "INTEGER");
+        self.add_column("entries", "access_offset", "INTEGER");
+        self.add_column("entries", "modified_date", "TEXT");
+        self.add_column("entries", "modified_offset", "INTEGER");
+        self.add_column("entries", "pinned", "INTEGER NOT NULL DEFAULT 0");
+        self.add_column("entries", "open_count", "INTEGER NOT NULL DEFAULT 0");
+
+        self.conn.execute_batch("
+            CREATE INDEX IF NOT EXISTS entries_entry_date ON entries (entry_date);
+            CREATE INDEX IF NOT EXISTS entries_access_date ON entries (access_date);
+        ").expect("Could not add date indices");
     
         self.conn.execute("
             CREATE TABLE IF NOT EXISTS tags (
@@ -71,10 +324,163 @@ impl EntryDB {
                 tag TEXT,
                 entry TEXT,
                 PRIMARY KEY (entry, tag),
-                FOREIGN KEY (tag) REFERENCES tags (name) ON DELETE CASCADE,
-                FOREIGN KEY (entry) REFERENCES entries (name) ON DELETE CASCADE
+                FOREIGN KEY (tag) REFERENCES tags (name) ON DELETE CASCADE ON UPDATE CASCADE,
+                FOREIGN KEY (entry) REFERENCES entries (name) ON DELETE CASCADE ON UPDATE CASCADE
             )
         ", ()).expect("Could not add tag reference table");
+
+        self.conn.execute("
+            CREATE TABLE IF NOT EXISTS people (
+                name TEXT PRIMARY KEY
+            )
+        ", ()).expect("Could not add people table");
+
+        self.conn.execute("
+            CREATE TABLE IF NOT EXISTS entry_people (
+                person TEXT,
+                entry TEXT,
+                PRIMARY KEY (entry, person),
+                FOREIGN KEY (person) REFERENCES people (name) ON DELETE CASCADE ON UPDATE CASCADE,
+                FOREIGN KEY (entry) REFERENCES entries (name) ON DELETE CASCADE ON UPDATE CASCADE
+            )
+        ", ()).expect("Could not add people reference table");
+
+        self.conn.execute("
+            CREATE TABLE IF NOT EXISTS links (
+                source TEXT,
+                target TEXT,
+                PRIMARY KEY (source, target),
+                FOREIGN KEY (source) REFERENCES entries (name) ON DELETE CASCADE ON UPDATE CASCADE
+            )
+        ", ()).expect("Could not add links table");
+
+        self.conn.execute("
+            CREATE TABLE IF NOT EXISTS projects (
+                name TEXT PRIMARY KEY NOT NULL
+            )
+        ", ()).expect("Could not add projects table");
+
+        self.conn.execute("
+            CREATE TABLE IF NOT EXISTS project_entries (
+                project TEXT,
+                entry TEXT,
+                position INTEGER NOT NULL,
+                PRIMARY KEY (project, entry),
+                FOREIGN KEY (project) REFERENCES projects (name) ON DELETE CASCADE ON UPDATE CASCADE,
+                FOREIGN KEY (entry) REFERENCES entries (name) ON DELETE CASCADE ON UPDATE CASCADE
+            )
+        ", ()).expect("Could not add project reference table");
+
+        self.conn.execute("
+            CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY NOT NULL,
+                value TEXT
+            )
+        ", ()).expect("Could not add settings table");
+
+        self.conn.execute("
+            CREATE TABLE IF NOT EXISTS access_log (
+                entry TEXT NOT NULL,
+                date TEXT NOT NULL,
+                offset INTEGER,
+                FOREIGN KEY (entry) REFERENCES entries (name) ON DELETE CASCADE ON UPDATE CASCADE
+            )
+        ", ()).expect("Could not add access log table");
+        self.conn.execute("CREATE INDEX IF NOT EXISTS access_log_entry ON access_log (entry)", ())
+            .expect("Could not add access log index");
+
+        self.conn.execute("
+            CREATE TABLE IF NOT EXISTS operations (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL,
+                entry TEXT NOT NULL,
+                data TEXT NOT NULL,
+                date TEXT NOT NULL,
+                batch INTEGER
+            )
+        ", ()).expect("Could not add operations table");
+        self.add_column("operations", "batch", "INTEGER");
+    }
+
+    /// Records a change in the undo log with what is needed to reverse it.
+    /// Only the most recent operations are kept.
+    fn log_operation(&self, kind: &str, entry: &str, data: Value) {
+        self.conn.execute(
+            "INSERT INTO operations (kind, entry, data, date) VALUES (?1, ?2, ?3, ?4)",
+            (kind, entry, data.to_string(), to_db_date(&chrono::offset::Local::now().into())))
+            .expect("Could not log operation");
+        self.conn.execute(
+            "DELETE FROM operations WHERE id <= (SELECT MAX(id) FROM operations) - ?1", (UNDO_HISTORY,))
+            .expect("Could not trim operation log");
+    }
+
+    /// Id of the newest operation in the undo log, 0 if it is empty
+    pub fn last_operation_id(&self) -> i64 {
+        self.conn.query_row("SELECT COALESCE(MAX(id), 0) FROM operations", [], |row| row.get(0))
+            .expect("Could not read operation log")
+    }
+
+    /// Marks the operations logged after `after` as one batch, which is undone as a single action
+    pub fn group_operations(&self, after: i64) {
+        self.conn.execute(
+            "UPDATE operations SET batch = (SELECT MIN(id) FROM operations WHERE id > ?1) WHERE id > ?1", (after,))
+            .expect("Could not group operations");
+    }
+
+    /// An entry's file and row as they are now, for putting them back on undo
+    fn snapshot(&self, entry: &Entry) -> Value {
+        let mut stmt = self.conn.prepare("SELECT project, position FROM project_entries WHERE entry = ?1")
+            .expect("Could not select project entries in DB");
+        let projects = stmt.query_map((&entry.name,), |row| Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?)))
+            .expect("Error reading project entries")
+            .filter_map(|p| p.ok())
+            .collect::<Vec<(String, u32)>>();
+        let open_count: u32 = self.conn.query_row("SELECT open_count FROM entries WHERE name = ?1", (&entry.name,), |row| row.get(0))
+            .unwrap_or(0);
+
+        json!({
+            "content": fs::read_to_string(&entry.path).unwrap_or_default(),
+            "number": entry.number,
+            "entry_date": entry.entry_string(),
+            "entry_offset": entry.entry_offset(),
+            "access_date": entry.access_string(),
+            "access_offset": entry.access_offset(),
+            "status": entry.status,
+            "pinned": entry.pinned,
+            "open_count": open_count,
+            "projects": projects,
+        })
+    }
+
+    pub fn get_setting(&self, key: &str) -> Option<String> {
+        self.conn.query_row("SELECT value FROM settings WHERE key = ?1", (key,), |row| row.get(0)).ok()
+    }
+
+    pub fn set_setting(&self, key: &str, value: &str) {
+        self.conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2) ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            (key, value))
+            .expect("Could not save setting");
+    }
+
+    /// Format of new entries in this journal, plain text unless set with `journal format`
+    pub fn default_format(&self) -> Format {
+        self.get_setting("default_format").and_then(|f| Format::parse(&f)).unwrap_or(Format::Text)
+    }
+
+    /// Adds a column to a table created by an older version of the schema
+    fn add_column(&self, table: &str, column: &str, definition: &str) {
+        let mut stmt = self.conn.prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))
+            .expect("Could not read table info");
+        let exists = stmt.query_map([], |row| row.get::<_, String>(0))
+            .expect("Could not read table columns")
+            .filter_map(|c| c.ok())
+            .any(|c| c == column);
+
+        if !exists {
+            self.conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), ())
+                .expect("Could not migrate table");
+        }
     }
 
     pub fn rebuild_database(&self) {
@@ -90,27 +496,16 @@ impl EntryDB {
     
         sort_entries_by_number(&mut entries);
     
-        for entry in entries {
-            let entry_string = entry.entry_string();
-            let access_string = entry.access_string();
-            
-            if let Some(number) = entry.number {
-                self.conn.execute(
-                    "INSERT INTO entries (number, name, entry_date, access_date) VALUES (?1, ?2, ?3, ?4)", 
-                    (number, entry.name, entry_string, access_string)).expect("Could not add table");
-            } else {
-                self.conn.execute(
-                    "INSERT INTO entries (name, entry_date, access_date) VALUES (?1, ?2, ?3)", 
-                    (entry.name, entry_string, access_string)).expect("Could not add table");
-            }
-            
+        for mut entry in entries {
+            self.add_entry_to_db(&entry);
+            // Restore tags stored in the file's front matter
+            self.index_entry(&mut entry);
         }
     
         let mut stmt = self.conn.prepare("SELECT * FROM entries").expect("Entries not found");
     
         let entry_rows = stmt.query_map([], |row| {
-            let entry = Entry::build_from_row(&self.config.entry_dir, &row);
-            entry
+            Entry::build_from_row(&self.config.entry_dir, row)
         }).expect("Error reading rows");
         
         for row in entry_rows {
@@ -118,55 +513,180 @@ impl EntryDB {
         }
     }
 
+    /// Marks an entry as opened now, counting the open and adding it to the access log.
+    /// Only the most recent opens are kept in the log.
     pub fn update_entry_access_date(&self, entry: &mut Entry) -> &Self {
         entry.access_date = Some(chrono::offset::Local::now().into());
-        if let Some(date) = entry.access_date {
-            self.conn.execute(
-                "UPDATE entries SET access_date = ?1 WHERE name = ?2", 
-                (date.to_rfc2822(), entry.name.clone()))
-                .expect("Could not update access date in DB");
-        }
+        self.conn.execute(
+            "UPDATE entries SET access_date = ?1, access_offset = ?2, open_count = open_count + 1 WHERE name = ?3", 
+            (entry.access_string(), entry.access_offset(), entry.name.clone()))
+            .expect("Could not update access date in DB");
+        self.conn.execute(
+            "INSERT INTO access_log (entry, date, offset) VALUES (?1, ?2, ?3)",
+            (&entry.name, entry.access_string(), entry.access_offset()))
+            .expect("Could not log entry access");
+        self.conn.execute(
+            "DELETE FROM access_log WHERE rowid <= (SELECT MAX(rowid) FROM access_log) - ?1", (ACCESS_HISTORY,))
+            .expect("Could not trim access log");
         self
     }
 
+    /// The `count` most recently opened entries, newest first, with how many times each has been opened
+    pub fn get_recent_entries(&self, count: usize) -> Vec<(Entry, u32)> {
+        let mut stmt = self.conn.prepare("
+            SELECT e.*, e.open_count AS opens, MAX(l.date) AS last_open FROM access_log l
+            JOIN entries e ON e.name = l.entry
+            GROUP BY l.entry
+            ORDER BY last_open DESC
+            LIMIT ?1
+        ").expect("Could not select recent entries in DB");
+        let entries = stmt.query_map((count as u32,), |row| {
+            Ok((Entry::build_from_row(&self.config.entry_dir, row)?, row.get("opens")?))
+        }).expect("Error reading recent entries");
+
+        entries.filter_map(|val| val.ok()).collect()
+    }
+
     pub fn get_entries(&self) -> Vec<Entry> {
-        let mut stmt = self.conn.prepare("SELECT * FROM entries").expect("Could not select entries in DB");
-        let entries = stmt.query_map([], |row| {
-            Entry::build_from_row(&self.config.entry_dir, &row)
+        self.query_entries("SELECT * FROM entries", [])
+    }
+
+    fn query_entries<P: rusqlite::Params>(&self, query: &str, params: P) -> Vec<Entry> {
+        let mut stmt = self.conn.prepare(query).expect("Could not select entries in DB");
+        let entries = stmt.query_map(params, |row| {
+            Entry::build_from_row(&self.config.entry_dir, row)
         }).expect("Error reading entries");
 
-        entries.into_iter()
-                .filter_map(|val| val.ok())
-                .collect::<Vec<Entry>>()
+        entries.filter_map(|val| val.ok()).collect::<Vec<Entry>>()
     }
-    //pub fn get_entries(&self)
 
-    pub fn delete_entry(&self, entry: &mut Entry) {
-        self.conn.execute(
-            "DELETE FROM entries WHERE name = ?1", 
-            (entry.name.clone(),))
-            .expect("Could not delete entry from DB");
+    /// Pinned entries, in number order
+    pub fn get_pinned_entries(&self) -> Vec<Entry> {
+        let mut entries = self.query_entries("SELECT * FROM entries WHERE pinned = 1", []);
+        sort_entries_by_number(&mut entries);
+        entries
+    }
+
+    pub fn set_pinned(&self, entry: &mut Entry, pinned: bool) {
+        self.conn.execute("UPDATE entries SET pinned = ?1 WHERE name = ?2", (pinned, &entry.name))
+            .expect("Could not pin entry");
+        entry.pinned = pinned;
+    }
+
+    /// The most recently opened entry
+    pub fn get_last_accessed(&self) -> Option<Entry> {
+        self.query_entries(
+            "SELECT * FROM entries WHERE access_date IS NOT NULL ORDER BY access_date DESC LIMIT 1", [])
+            .into_iter()
+            .next()
+    }
+
+    /// Entries whose date falls within `since..until`, oldest first. Either bound may be left open.
+    pub fn get_entries_between(&self, field: DateField, since: Option<DateTime<FixedOffset>>, until: Option<DateTime<FixedOffset>>) -> Vec<Entry> {
+        let query = format!(
+            "SELECT * FROM entries WHERE {column} IS NOT NULL AND {column} >= ?1 AND {column} < ?2 ORDER BY {column}",
+            column = field.column());
+        let since = since.as_ref().map(to_db_date).unwrap_or_default();
+        // Every stored date sorts before "~"
+        let until = until.as_ref().map(to_db_date).unwrap_or_else(|| "~".to_string());
+        self.query_entries(&query, (since, until))
+    }
 
-        fs::remove_file(&entry.path).expect("Could not delete file");
+    /// Deletes an entry's row and its file as one operation.
+    /// The file is moved aside first so it can be put back if the DB transaction fails.
+    pub fn delete_entry(&self, entry: &mut Entry) -> Result<(), EntryError> {
+        if lock::entry_locked(&self.config, &entry.name) {
+            return Err(EntryError::Locked(entry.name.clone()));
+        }
+        let snapshot = self.snapshot(entry);
+        // Rows referencing the entry are removed by the ON DELETE CASCADE foreign keys
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM entries WHERE name = ?1", (&entry.name,))?;
+
+        let trash = self.config.get_entry_path(&format!(".{}.deleted", entry.name));
+        // A file that is already gone leaves only the row to delete
+        let trashed = match fs::rename(&entry.path, &trash) {
+            Ok(_) => true,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+            Err(e) => return Err(e.into()),
+        };
+        if let Err(e) = tx.commit() {
+            if trashed {
+                fs::rename(&trash, &entry.path)?;
+            }
+            return Err(e.into());
+        }
+        if trashed {
+            fs::remove_file(&trash)?;
+        }
+
+        self.log_operation("delete", &entry.name, snapshot);
+        git::commit(&self.config, &format!("Delete {}", entry.name));
+        Ok(())
     }
 
     pub fn add_entry_to_db(&self, entry: &Entry) {
-        if let Some(number) = entry.number {
-            self.conn.execute(
-                "INSERT INTO entries (number, name, entry_date, access_date) VALUES (?1, ?2, ?3, ?4)", 
-                (number, entry.name.clone(), entry.entry_string(), entry.access_string())).expect("Could not add entry to DB");
-        } else {
-            self.conn.execute(
-                "INSERT INTO entries (name, entry_date, access_date) VALUES (?1, ?2, ?3)", 
-                (entry.name.clone(), entry.entry_string(), entry.access_string())).expect("Could not add entry to DB");
+        self.try_add_entry_to_db(entry).expect("Could not add entry to DB");
+    }
+
+    /// `add_entry_to_db` for callers that need to recover when the row can't be added
+    pub fn try_add_entry_to_db(&self, entry: &Entry) -> Result<usize> {
+        self.insert_entry(entry, "")
+    }
+
+    fn insert_entry(&self, entry: &Entry, conflict: &str) -> Result<usize> {
+        self.conn.execute(
+            &format!("INSERT INTO entries (number, name, entry_date, entry_offset, access_date, access_offset, title, status)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8) {}", conflict),
+            (entry.number, entry.name.clone(), entry.entry_string(), entry.entry_offset(),
+             entry.access_string(), entry.access_offset(), &entry.title, &entry.status))
+    }
+
+    pub fn get_entry_tags(&self, entry: &Entry) -> Vec<String> {
+        let mut stmt = self.conn.prepare("SELECT tag FROM entry_tags WHERE entry = ?1 ORDER BY tag")
+            .expect("Could not select entry tags in DB");
+        let tags = stmt.query_map((&entry.name,), |row| row.get(0)).expect("Error reading entry tags");
+
+        tags.filter_map(|val| val.ok()).collect::<Vec<String>>()
+    }
+
+    /// Reads the front matter of an entry's file and copies its date, status and tags into the DB.
+    /// Entries without front matter are left untouched.
+    pub fn sync_front_matter(&self, entry: &mut Entry) {
+        let content = match fs::read_to_string(&entry.path) {
+            Ok(content) => content,
+            Err(_) => return,
+        };
+        let front_matter = match FrontMatter::parse(&content).0 {
+            Some(front_matter) => front_matter,
+            None => return,
+        };
+
+        entry.entry_date = front_matter.date.or(entry.entry_date);
+        entry.status = front_matter.status;
+        self.conn.execute(
+            "UPDATE entries SET entry_date = ?1, entry_offset = ?2, status = ?3 WHERE name = ?4",
+            (entry.entry_string(), entry.entry_offset(), &entry.status, &entry.name))
+            .expect("Could not update entry metadata in DB");
+
+        for tag in front_matter.tags.iter() {
+            self.assign_tag(entry, &tag.to_lowercase()).expect("Could not assign tag");
         }
     }
 
-    pub fn create_custom_entry(&self, entry_name: &str) -> Entry {
-        let entry = Entry::create_custom(&self.config, entry_name);
-        entry.initialize();
+    /// Creates an entry with a name chosen by the user, adding `extension` if the name has none
+    pub fn create_custom_entry(&self, entry_name: &str, extension: Option<&str>) -> Result<Entry, EntryError> {
+        let entry_name = validate_name(entry_name, extension)?;
+        if self.conn.prepare("SELECT name FROM entries WHERE name = ?1")?.exists((&entry_name,))? {
+            return Err(EntryError::NameTaken(entry_name));
+        }
+        let entry = Entry::create_custom(&self.config, &entry_name);
+        // The row goes in first so `journal watch` sees a known entry once the file appears
         self.add_entry_to_db(&entry);
-        entry
+        entry.initialize();
+        self.log_operation("create", &entry.name, self.snapshot(&entry));
+        git::commit(&self.config, &format!("Create {}", entry.name));
+        Ok(entry)
     }
 
     fn get_largest_entry_num(&self) -> u32 {
@@ -180,9 +700,11 @@ impl EntryDB {
 
     pub fn create_default_entry(&self) -> Entry {
         let number = self.get_largest_entry_num();
-        let entry = Entry::create_default(number, &self.config);
-        entry.initialize();
+        let entry = Entry::create_default(number, self.default_format(), &self.config);
         self.add_entry_to_db(&entry);
+        entry.initialize();
+        self.log_operation("create", &entry.name, self.snapshot(&entry));
+        git::commit(&self.config, &format!("Create {}", entry.name));
         entry
     }
 
@@ -193,12 +715,7 @@ impl EntryDB {
 
     fn tag_exists(&self, tag: &str) -> bool {
         let mut stmt = self.conn.prepare("SELECT name FROM tags WHERE name = ?1").expect("Could not prepare tag check statement");
-        let val = stmt.query((tag,));
-        if let Ok(_) = val {
-            return true;
-        }
-        false
-
+        stmt.exists((tag,)).unwrap_or(false)
     }
 
     pub fn assign_tag(&self, entry: &Entry, tag: &str) -> Result<()> {
@@ -206,30 +723,386 @@ impl EntryDB {
             self.add_tag(tag).expect("Could not add tag");
         }
         self.conn.execute(
-            "INSERT INTO entry_tags (tag, entry) VALUES (?1, ?2)", 
+            "INSERT OR IGNORE INTO entry_tags (tag, entry) VALUES (?1, ?2)", 
             (tag, &entry.name))?;
         
         Ok(())
     }
 
-    pub fn change_name(&self, entry: &mut Entry, new_name: &str) {
-        // Check if name already exists
+    /// Adds the inline `#tags` of an entry to its tags and replaces its `@people` mentions
+    pub fn index_mentions(&self, entry: &Entry) {
+        let body = read_entry_body(&entry.path);
+
+        for tag in extract_hashtags(&body) {
+            self.assign_tag(entry, &tag).expect("Could not assign tag");
+        }
+
+        self.conn.execute("DELETE FROM entry_people WHERE entry = ?1", (&entry.name,))
+            .expect("Could not clear entry mentions");
+        for person in extract_mentions(&body) {
+            self.conn.execute("INSERT OR IGNORE INTO people (name) VALUES (?1)", (&person,))
+                .expect("Could not add person");
+            self.conn.execute(
+                "INSERT OR IGNORE INTO entry_people (person, entry) VALUES (?1, ?2)",
+                (&person, &entry.name))
+                .expect("Could not add mention");
+        }
+    }
+
+    /// Replaces the outgoing `[[links]]` recorded for an entry
+    pub fn index_links(&self, entry: &Entry) {
+        let body = read_entry_body(&entry.path);
 
+        self.conn.execute("DELETE FROM links WHERE source = ?1", (&entry.name,))
+            .expect("Could not clear entry links");
+        for target in extract_links(&body) {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO links (source, target) VALUES (?1, ?2)",
+                (&entry.name, &target))
+                .expect("Could not add link");
+        }
+    }
 
+    /// Titles come from the front matter, or else from the first line of the entry
+    fn index_title(&self, entry: &mut Entry) {
+        let content = match fs::read_to_string(&entry.path) {
+            Ok(content) => content,
+            Err(_) => return,
+        };
+        let explicit = FrontMatter::parse(&content).0.and_then(|m| m.title);
+        entry.title = explicit.or_else(|| content_title(strip_header(&content)));
+        self.conn.execute("UPDATE entries SET title = ?1 WHERE name = ?2", (&entry.title, &entry.name))
+            .expect("Could not update entry title in DB");
+    }
+
+    /// Gives an entry its own title by writing it to the front matter.
+    /// `None` goes back to the title taken from the entry's first line.
+    pub fn set_title(&self, entry: &mut Entry, title: Option<&str>) -> Result<(), EntryError> {
+        if lock::entry_locked(&self.config, &entry.name) {
+            return Err(EntryError::Locked(entry.name.clone()));
+        }
+        let title = title.map(|t| t.trim()).filter(|t| !t.is_empty());
+
+        let snapshot = self.snapshot(entry);
+        let content = fs::read_to_string(&entry.path)?;
+        fs::write(&entry.path, FrontMatter::set_value(&content, "title", title, Format::from_name(&entry.name)))?;
+        self.index_title(entry);
+        self.log_operation("title", &entry.name, snapshot);
+        git::commit(&self.config, &format!("Retitle {}", entry.name));
+        Ok(())
+    }
+
+    /// Adds and removes tags in an entry's front matter.
+    /// Inline `#tags` stay, since they are part of the text.
+    pub fn retag_entry(&self, entry: &mut Entry, add: &[String], remove: &[String]) -> Result<(), EntryError> {
+        if lock::entry_locked(&self.config, &entry.name) {
+            return Err(EntryError::Locked(entry.name.clone()));
+        }
+        let snapshot = self.snapshot(entry);
+        let content = fs::read_to_string(&entry.path)?;
+        let mut tags = FrontMatter::parse(&content).0.map(|m| m.tags).unwrap_or_default();
+        tags.retain(|t| !remove.contains(&t.to_lowercase()));
+        for tag in add {
+            if !tags.iter().any(|t| t.to_lowercase() == *tag) {
+                tags.push(tag.clone());
+            }
+        }
+
+        fs::write(&entry.path, FrontMatter::set_tags(&content, &tags, Format::from_name(&entry.name)))?;
+        self.index_entry(entry);
+        self.log_operation("tags", &entry.name, snapshot);
+        git::commit(&self.config, &format!("Retag {}", entry.name));
+        Ok(())
+    }
+
+    /// Updates everything the DB derives from an entry's file, run after it has been edited
+    pub fn index_entry(&self, entry: &mut Entry) {
+        // Tags come from both the front matter and the text, so they are cleared once before either is read
+        self.conn.execute("DELETE FROM entry_tags WHERE entry = ?1", (&entry.name,))
+            .expect("Could not clear entry tags");
+        self.sync_front_matter(entry);
+        self.index_title(entry);
+        self.index_mentions(entry);
+        self.index_links(entry);
+        self.update_modified_date(entry);
+    }
+
+    fn update_modified_date(&self, entry: &mut Entry) {
+        entry.modified_date = modified_date(&entry.path);
         self.conn.execute(
-            "UPDATE entries SET name = ?1 WHERE name = ?2", 
-            (new_name, &entry.name)).expect("Could not update name");
-        
-        // Update filepath
-        let path = self.config.get_entry_path(&entry.name);
-        let new_path = self.config.get_entry_path(&new_name);
-        std::fs::rename(path, new_path).expect("Could not rename filepath");
-        
-        // Change the name of the entry
+            "UPDATE entries SET modified_date = ?1, modified_offset = ?2 WHERE name = ?3",
+            (entry.modified_string(), entry.modified_offset(), &entry.name))
+            .expect("Could not update modified date in DB");
+    }
+
+    /// Adds an entry for a file put in the entry directory by hand or by another program
+    pub fn add_untracked_file(&self, name: &str) -> Entry {
+        let mut entry = self.untracked_entry(name);
+        self.add_entry_to_db(&entry);
+        self.index_entry(&mut entry);
+        entry
+    }
+
+    /// `add_untracked_file` for `journal watch`, which can race the journal creating the same entry.
+    /// A row added in the meantime is kept and brought up to date with the file.
+    pub fn sync_untracked_file(&self, name: &str) -> Entry {
+        let mut entry = self.untracked_entry(name);
+        let conflict = "ON CONFLICT (name) DO NOTHING";
+        // Another session may have taken the number since it was checked, the entry then goes in without one
+        match self.insert_entry(&entry, conflict) {
+            Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == rusqlite::ErrorCode::ConstraintViolation => {
+                entry.number = None;
+                self.insert_entry(&entry, conflict).expect("Could not add entry to DB");
+            },
+            result => {
+                result.expect("Could not add entry to DB");
+            },
+        }
+        self.index_entry(&mut entry);
+        entry
+    }
+
+    fn untracked_entry(&self, name: &str) -> Entry {
+        let mut entry = Entry::from_file(&self.config.entry_dir, name);
+        // Keep the number only if it is free
+        if entry.number.is_some_and(|n| self.get_entries().iter().any(|e| e.number == Some(n))) {
+            entry.number = None;
+        }
+        entry
+    }
+
+    /// Follows a file renamed outside the journal. Returns false if the old name wasn't tracked,
+    /// e.g. because the journal did the renaming itself.
+    pub fn entry_renamed(&self, old_name: &str, new_name: &str) -> Result<bool, EntryError> {
+        let mut exists = self.conn.prepare("SELECT name FROM entries WHERE name = ?1")?;
+        if !exists.exists((old_name,))? {
+            return Ok(false);
+        }
+        if exists.exists((new_name,))? {
+            return Err(EntryError::NameTaken(new_name.to_string()));
+        }
+
+        self.conn.execute("UPDATE entries SET name = ?1 WHERE name = ?2", (new_name, old_name))?;
+        self.rewrite_links_to(old_name, new_name);
+        Ok(true)
+    }
+
+    /// Drops the row of an entry whose file was removed outside the journal
+    pub fn forget_entry(&self, name: &str) -> Result<bool, EntryError> {
+        Ok(self.conn.execute("DELETE FROM entries WHERE name = ?1", (name,))? > 0)
+    }
+
+    fn get_links(&self) -> Vec<(String, String)> {
+        let mut stmt = self.conn.prepare("SELECT source, target FROM links ORDER BY source, target")
+            .expect("Could not select links in DB");
+        let links = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .expect("Error reading links");
+
+        links.filter_map(|val| val.ok()).collect::<Vec<(String, String)>>()
+    }
+
+    /// Names of the entries linking to `entry`
+    pub fn get_backlinks(&self, entry: &Entry) -> Vec<String> {
+        let mut sources = self.get_links()
+            .into_iter()
+            .filter(|(_, target)| link_matches(target, entry))
+            .map(|(source, _)| source)
+            .collect::<Vec<String>>();
+        sources.dedup();
+        sources
+    }
+
+    /// Links whose target doesn't match any entry, as (source, target) pairs
+    pub fn get_broken_links(&self) -> Vec<(String, String)> {
+        let entries = self.get_entries();
+        self.get_links()
+            .into_iter()
+            .filter(|(_, target)| !entries.iter().any(|e| link_matches(target, e)))
+            .collect()
+    }
+
+    /// Rewrites `[[links]]` to a renamed entry in every entry's file
+    fn rewrite_links_to(&self, old_name: &str, new_name: &str) {
+        for entry in self.get_entries() {
+            let content = match fs::read_to_string(&entry.path) {
+                Ok(content) => content,
+                Err(_) => continue,
+            };
+            let rewritten = rewrite_links(&content, old_name, new_name);
+            if rewritten != content {
+                fs::write(&entry.path, rewritten).expect("Could not rewrite links");
+                self.index_links(&entry);
+            }
+        }
+    }
+
+    fn get_index(&self, query: &str) -> Vec<(String, Vec<String>)> {
+        let mut stmt = self.conn.prepare(query).expect("Could not prepare index query");
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .expect("Error reading index");
+
+        let mut index: Vec<(String, Vec<String>)> = Vec::new();
+        for (key, entry) in rows.filter_map(|r| r.ok()) {
+            match index.last_mut() {
+                Some((last, entries)) if *last == key => entries.push(entry),
+                _ => index.push((key, vec![entry])),
+            }
+        }
+        index
+    }
+
+    /// Every tag with the entries it is assigned to
+    pub fn get_tag_index(&self) -> Vec<(String, Vec<String>)> {
+        self.get_index("SELECT tag, entry FROM entry_tags ORDER BY tag, entry")
+    }
+
+    /// Every mentioned person with the entries that mention them
+    pub fn get_people_index(&self) -> Vec<(String, Vec<String>)> {
+        self.get_index("SELECT person, entry FROM entry_people ORDER BY person, entry")
+    }
+
+    /// Renames an entry's row and its file as one operation,
+    /// then rewrites the links pointing at it
+    pub fn change_name(&self, entry: &mut Entry, new_name: &str) -> Result<(), EntryError> {
+        if lock::entry_locked(&self.config, &entry.name) {
+            return Err(EntryError::Locked(entry.name.clone()));
+        }
+        // A new name without an extension keeps the current one
+        let extension = Path::new(&entry.name).extension().map(|e| e.to_string_lossy().to_string());
+        let new_name = &validate_name(new_name, extension.as_deref())?;
+        let new_path = self.config.get_entry_path(new_name);
+        let taken = self.conn.prepare("SELECT name FROM entries WHERE name = ?1")?.exists((new_name,))?;
+        if taken || Path::new(&new_path).exists() {
+            return Err(EntryError::NameTaken(new_name.to_string()));
+        }
+
+        // Rows referencing the entry follow through the ON UPDATE CASCADE foreign keys
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("UPDATE entries SET name = ?1 WHERE name = ?2", (new_name, &entry.name))?;
+
+        fs::rename(&entry.path, &new_path)?;
+        if let Err(e) = tx.commit() {
+            fs::rename(&new_path, &entry.path)?;
+            return Err(e.into());
+        }
+
+        self.rewrite_links_to(&entry.name, new_name);
+        self.log_operation("rename", new_name, json!({ "from": entry.name }));
+        git::commit(&self.config, &format!("Rename {} to {}", entry.name, new_name));
+
         entry.name = new_name.to_string();
+        entry.path = new_path;
+        Ok(())
+    }
+
+    /// Backdates an entry and/or gives it a number. The front matter date is moved too, since it
+    /// would otherwise win on the next edit, and with `header` so is the date line under it.
+    pub fn update_metadata(&self, entry: &mut Entry, date: Option<DateTime<FixedOffset>>, number: Option<u32>, header: bool) -> Result<(), EntryError> {
+        if lock::entry_locked(&self.config, &entry.name) {
+            return Err(EntryError::Locked(entry.name.clone()));
+        }
+        if let Some(number) = number {
+            let taken = self.conn.prepare("SELECT name FROM entries WHERE number = ?1 AND name != ?2")?
+                .exists((number, &entry.name))?;
+            if taken {
+                return Err(EntryError::NumberTaken(number));
+            }
+        }
 
-        // get entry path and rename the file
+        let mut snapshot = self.snapshot(entry);
+        // Undo needs to know whether the date line was moved too
+        snapshot["header"] = json!(header);
+        let entry_date = date.or(entry.entry_date);
+        let number = number.or(entry.number);
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE entries SET entry_date = ?1, entry_offset = ?2, number = ?3 WHERE name = ?4",
+            (entry_date.as_ref().map(to_db_date), entry_date.map(|d| d.offset().local_minus_utc()), number, &entry.name))?;
 
+        // Only a new date touches the file, which is put back as it was if the DB change can't be committed
+        let original = match date {
+            Some(date) => {
+                let original = fs::read_to_string(&entry.path)?;
+                rewrite_entry_date(&entry.path, &date, header)?;
+                Some(original)
+            },
+            None => None,
+        };
+        if let Err(e) = tx.commit() {
+            if let Some(original) = original {
+                fs::write(&entry.path, original)?;
+            }
+            return Err(e.into());
+        }
+
+        entry.entry_date = entry_date;
+        entry.number = number;
+        self.log_operation("metadata", &entry.name, snapshot);
+        git::commit(&self.config, &format!("Edit metadata of {}", entry.name));
+        Ok(())
+    }
+
+    pub fn add_project(&self, project: &str) -> Result<()> {
+        self.conn.execute("INSERT OR IGNORE INTO projects (name) VALUES (?1)", (project,))?;
+        Ok(())
     }
-}
 
+    pub fn get_projects(&self) -> Vec<String> {
+        let mut stmt = self.conn.prepare("SELECT name FROM projects ORDER BY name").expect("Could not select projects in DB");
+        let projects = stmt.query_map([], |row| row.get(0)).expect("Error reading projects");
+
+        projects.filter_map(|val| val.ok()).collect::<Vec<String>>()
+    }
+
+    /// Returns the entries of a project in manuscript order
+    pub fn get_project_entries(&self, project: &str) -> Vec<Entry> {
+        let mut stmt = self.conn.prepare("
+            SELECT e.* FROM entries e
+            JOIN project_entries p ON p.entry = e.name
+            WHERE p.project = ?1
+            ORDER BY p.position
+        ").expect("Could not select project entries in DB");
+        let entries = stmt.query_map((project,), |row| {
+            Entry::build_from_row(&self.config.entry_dir, row)
+        }).expect("Error reading project entries");
+
+        entries.filter_map(|val| val.ok()).collect::<Vec<Entry>>()
+    }
+
+    fn set_project_order(&self, project: &str, names: &[String]) -> Result<()> {
+        self.conn.execute("DELETE FROM project_entries WHERE project = ?1", (project,))?;
+        for (position, name) in names.iter().enumerate() {
+            self.conn.execute(
+                "INSERT INTO project_entries (project, entry, position) VALUES (?1, ?2, ?3)",
+                (project, name, position as u32 + 1))?;
+        }
+        Ok(())
+    }
+
+    /// Places an entry in a project at the given (1 based) position, appending it if no position is given.
+    /// Entries already in the project are moved.
+    pub fn add_to_project(&self, project: &str, entry: &Entry, position: Option<usize>) -> Result<()> {
+        self.add_project(project)?;
+        let mut names = self.get_project_entries(project)
+            .into_iter()
+            .map(|e| e.name)
+            .filter(|name| name != &entry.name)
+            .collect::<Vec<String>>();
+
+        let index = match position {
+            Some(pos) => pos.saturating_sub(1).min(names.len()),
+            None => names.len(),
+        };
+        names.insert(index, entry.name.clone());
+        self.set_project_order(project, &names)
+    }
+
+    pub fn remove_from_project(&self, project: &str, entry: &Entry) -> Result<()> {
+        let names = self.get_project_entries(project)
+            .into_iter()
+            .map(|e| e.name)
+            .filter(|name| name != &entry.name)
+            .collect::<Vec<String>>();
+        self.set_project_order(project, &names)
+    }
+}
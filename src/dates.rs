@@ -0,0 +1,182 @@
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, Months, NaiveDate, TimeZone, Weekday};
+use crate::database::{DateField, EntryDB};
+use crate::file_operations::Entry;
+use crate::front_matter::parse_date;
+
+fn start_of_day(date: NaiveDate) -> Option<DateTime<FixedOffset>> {
+    Local.from_local_datetime(&date.and_hms_opt(0, 0, 0)?).earliest().map(|d| d.into())
+}
+
+fn weekday(word: &str) -> Option<Weekday> {
+    match word {
+        "monday" | "mon" => Some(Weekday::Mon),
+        "tuesday" | "tue" => Some(Weekday::Tue),
+        "wednesday" | "wed" => Some(Weekday::Wed),
+        "thursday" | "thu" => Some(Weekday::Thu),
+        "friday" | "fri" => Some(Weekday::Fri),
+        "saturday" | "sat" => Some(Weekday::Sat),
+        "sunday" | "sun" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// Parses a span such as `7d`, `2w`, `3m` or `1y`
+pub fn parse_duration(value: &str, now: DateTime<FixedOffset>) -> Option<DateTime<FixedOffset>> {
+    let value = value.trim().to_lowercase();
+    let split = value.find(|c: char| !c.is_ascii_digit())?;
+    let (count, unit) = value.split_at(split);
+    let count = count.parse::<u32>().ok()?;
+
+    match unit.trim() {
+        "h" | "hour" | "hours" => Some(now - Duration::hours(count as i64)),
+        "d" | "day" | "days" => Some(now - Duration::days(count as i64)),
+        "w" | "week" | "weeks" => Some(now - Duration::weeks(count as i64)),
+        "m" | "month" | "months" => now.checked_sub_months(Months::new(count)),
+        "y" | "year" | "years" => now.checked_sub_months(Months::new(count * 12)),
+        _ => None,
+    }
+}
+
+/// Turns a date expression into the span of time it covers, as `[start, end)`.
+/// Understands `2026-03-04`, `2026-03`, `2026`, `today`, `yesterday`, weekday names (`monday`, `last monday`),
+/// `this/last week`, `this/last month`, `this/last year` and `N days/weeks/months ago`.
+pub fn parse_span(expression: &str, today: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+    let expression = expression.trim().to_lowercase();
+    let words = expression.split_whitespace().collect::<Vec<&str>>();
+    let day = |date: NaiveDate| Some((date, date.succ_opt()?));
+
+    if let Ok(date) = NaiveDate::parse_from_str(&expression, "%Y-%m-%d") {
+        return day(date);
+    }
+    if let Ok(month) = NaiveDate::parse_from_str(&format!("{}-01", expression), "%Y-%m-%d") {
+        return Some((month, month.checked_add_months(Months::new(1))?));
+    }
+    if let Ok(year) = expression.parse::<i32>() {
+        return Some((NaiveDate::from_ymd_opt(year, 1, 1)?, NaiveDate::from_ymd_opt(year + 1, 1, 1)?));
+    }
+
+    let week_start = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    let month_start = today.with_day(1)?;
+    let year_start = NaiveDate::from_ymd_opt(today.year(), 1, 1)?;
+
+    match words.as_slice() {
+        ["today"] => day(today),
+        ["yesterday"] => day(today.pred_opt()?),
+        ["this", "week"] => Some((week_start, week_start + Duration::weeks(1))),
+        ["last", "week"] => Some((week_start - Duration::weeks(1), week_start)),
+        ["this", "month"] => Some((month_start, month_start.checked_add_months(Months::new(1))?)),
+        ["last", "month"] => Some((month_start.checked_sub_months(Months::new(1))?, month_start)),
+        ["this", "year"] => Some((year_start, year_start.checked_add_months(Months::new(12))?)),
+        ["last", "year"] => Some((year_start.checked_sub_months(Months::new(12))?, year_start)),
+        [count, unit, "ago"] => {
+            let count = count.parse::<u32>().ok()?;
+            let date = match *unit {
+                "day" | "days" => today - Duration::days(count as i64),
+                "week" | "weeks" => today - Duration::weeks(count as i64),
+                "month" | "months" => today.checked_sub_months(Months::new(count))?,
+                "year" | "years" => today.checked_sub_months(Months::new(count * 12))?,
+                _ => return None,
+            };
+            day(date)
+        },
+        // The most recent past occurrence of the weekday, never today
+        ["last", name] | [name] => {
+            let target = weekday(name)?;
+            let mut date = today.pred_opt()?;
+            while date.weekday() != target {
+                date = date.pred_opt()?;
+            }
+            day(date)
+        },
+        _ => None,
+    }
+}
+
+/// Parses a single point in time, e.g. `2026-03-04 21:30` or `yesterday`.
+/// Expressions naming a whole day keep the current time of day.
+pub fn parse_moment(expression: &str) -> Option<DateTime<FixedOffset>> {
+    if let Some(date) = parse_date(expression) {
+        return Some(date);
+    }
+    let now = Local::now();
+    let (day, _) = parse_span(expression, now.date_naive())?;
+    Local.from_local_datetime(&day.and_time(now.time())).earliest().map(|d| d.into())
+}
+
+/// A date range to list entries by, built from `--since`, `--until`, `--last` and `--on`
+#[derive(Debug, Clone, Copy)]
+pub struct DateFilter {
+    pub field: DateField,
+    pub since: Option<DateTime<FixedOffset>>,
+    pub until: Option<DateTime<FixedOffset>>,
+}
+
+impl DateFilter {
+    /// Reads filter keywords from a list of words, e.g. `["since", "2026-01-01", "until", "2026-03-31"]`.
+    /// Leading dashes are ignored so CLI flags and typed filters share the same syntax,
+    /// and a bare expression such as `last monday` is read as `on last monday`.
+    pub fn from_words(words: &[String]) -> Result<Self, String> {
+        let now: DateTime<FixedOffset> = Local::now().into();
+        let today = now.date_naive();
+        let mut filter = DateFilter { field: DateField::Created, since: None, until: None };
+
+        // Bare words before any keyword are read as `on`
+        let mut keyword = String::from("on");
+        let mut value: Vec<String> = Vec::new();
+        let mut clauses: Vec<(String, String)> = Vec::new();
+        for word in words {
+            let word = word.trim_start_matches('-').to_lowercase();
+            match word.as_str() {
+                // `since last monday` uses `last` as part of the date
+                "last" if value.is_empty() && keyword != "on" => value.push(word),
+                "since" | "until" | "on" | "last" => {
+                    if !value.is_empty() {
+                        clauses.push((keyword, value.join(" ")));
+                    }
+                    keyword = word;
+                    value.clear();
+                },
+                "accessed" => filter.field = DateField::Accessed,
+                "created" => filter.field = DateField::Created,
+                _ => value.push(word),
+            }
+        }
+        if !value.is_empty() {
+            clauses.push((keyword, value.join(" ")));
+        }
+
+        let start = |value: &str| {
+            parse_span(value, today).and_then(|(start, _)| start_of_day(start))
+                .ok_or(format!("Could not understand the date {:?}", value))
+        };
+        let end = |value: &str| {
+            parse_span(value, today).and_then(|(_, end)| start_of_day(end))
+                .ok_or(format!("Could not understand the date {:?}", value))
+        };
+
+        for (keyword, value) in clauses {
+            match keyword.as_str() {
+                "since" => filter.since = Some(start(&value)?),
+                "until" => filter.until = Some(end(&value)?),
+                // `last 7d` is a span back from now, `last monday` is a day
+                "last" => match parse_duration(&value, now) {
+                    Some(since) => filter.since = Some(since),
+                    None => {
+                        let value = format!("last {}", value);
+                        filter.since = Some(start(&value)?);
+                        filter.until = Some(end(&value)?);
+                    }
+                },
+                _ => {
+                    filter.since = Some(start(&value)?);
+                    filter.until = Some(end(&value)?);
+                },
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn apply(&self, db: &EntryDB) -> Vec<Entry> {
+        db.get_entries_between(self.field, self.since, self.until)
+    }
+}
@@ -1,16 +1,42 @@
-use std::{io::BufRead, path::Path};
-use std::io;
+use std::path::Path;
 use std::fs::OpenOptions;
 use std::fs;
 use std::io::Write;
 use regex::Regex;
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
 use rusqlite::{Result, Row, Connection};
 use crate::database::PathConfig;
+use crate::format::Format;
+use crate::front_matter::FrontMatter;
 
-fn get_time() -> String {
-    let dt = chrono::offset::Local::now();
-    dt.to_rfc2822()
+fn get_time() -> DateTime<FixedOffset> {
+    chrono::offset::Local::now().into()
+}
+
+const DB_DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+/// Dates are stored in UTC as sortable ISO-8601 strings, with the original UTC offset kept alongside
+pub fn to_db_date(date: &DateTime<FixedOffset>) -> String {
+    date.with_timezone(&Utc).format(DB_DATE_FORMAT).to_string()
+}
+
+/// Reads a date stored by `to_db_date` back in its original offset.
+/// RFC 2822 dates written by older versions are still accepted.
+pub fn from_db_date(value: &str, offset: Option<i32>) -> Option<DateTime<FixedOffset>> {
+    let value = value.trim();
+    match NaiveDateTime::parse_from_str(value, DB_DATE_FORMAT) {
+        Ok(naive) => {
+            let offset = offset.and_then(FixedOffset::east_opt).unwrap_or(FixedOffset::east_opt(0)?);
+            Some(Utc.from_utc_datetime(&naive).with_timezone(&offset))
+        },
+        Err(_) => DateTime::parse_from_rfc2822(value).ok(),
+    }
+}
+
+/// Modification time of a file in local time
+pub fn modified_date(filename: &str) -> Option<DateTime<FixedOffset>> {
+    let modified = fs::metadata(filename).and_then(|m| m.modified()).ok()?;
+    Some(DateTime::<Local>::from(modified).into())
 }
 
 pub fn file_exists(filename: &str) -> bool {
@@ -18,9 +44,30 @@ pub fn file_exists(filename: &str) -> bool {
     path.exists()
 }
 
+const SEPARATOR: &str = "======================================================================================================================";
+
 pub fn initialize_file(filename: &str) {
-    let current_date = get_time();
-    let text = format!("{}\n\n======================================================================================================================\n", current_date);
+    write_entry_file(filename, None, &get_time(), "");
+}
+
+/// Writes the optional front matter and the date header followed by `body`, in the style of the file's format:
+/// a date line and separator for plain text, a date heading for Markdown and `#+DATE:` for Org
+pub fn write_entry_file(filename: &str, front_matter: Option<&FrontMatter>, date: &DateTime<FixedOffset>, body: &str) {
+    let text = match Format::from_name(filename) {
+        Format::Text => {
+            let front_matter = front_matter.map(|m| m.render()).unwrap_or_default();
+            format!("{}{}\n\n{}\n{}", front_matter, date.to_rfc2822(), SEPARATOR, body)
+        },
+        Format::Markdown => {
+            let front_matter = front_matter.map(|m| m.render()).unwrap_or_default();
+            format!("{}# {}\n\n{}", front_matter, date.to_rfc2822(), body)
+        },
+        Format::Org => {
+            let mut front_matter = front_matter.cloned().unwrap_or_default();
+            front_matter.date = Some(*date);
+            format!("{}\n{}", front_matter.render_org(), body)
+        },
+    };
 
     let mut file = OpenOptions::new()
         .append(true)
@@ -28,7 +75,84 @@ pub fn initialize_file(filename: &str) {
         .open(filename)
         .expect("Could not open file");
 
-    file.write(text.as_bytes()).expect("Could not add text to file");
+    file.write_all(text.as_bytes()).expect("Could not add text to file");
+}
+
+/// Date of a header line written by `write_entry_file`, either plain or as a Markdown heading
+fn header_date(line: &str) -> Option<DateTime<FixedOffset>> {
+    let line = line.trim();
+    DateTime::parse_from_rfc2822(line.strip_prefix("# ").unwrap_or(line).trim()).ok()
+}
+
+/// Removes the front matter, date header and separator line written by `write_entry_file`
+pub fn strip_header(content: &str) -> &str {
+    let content = FrontMatter::parse(content).1.trim_start_matches('\n');
+    let first = match content.lines().next() {
+        Some(line) => line,
+        None => return content,
+    };
+    if header_date(first).is_none() {
+        return content;
+    }
+    if first.starts_with('#') {
+        return content[first.len()..].trim_start_matches('\n');
+    }
+
+    let mut offset = first.len();
+    for line in content[offset..].split_inclusive('\n') {
+        offset += line.len();
+        if line.trim() == SEPARATOR {
+            return content[offset..].trim_start_matches('\n');
+        }
+        if !line.trim().is_empty() {
+            break;
+        }
+    }
+    content
+}
+
+/// Moves an entry's front matter date to `date`, and with `header` the date line under it as well
+pub fn rewrite_entry_date(filename: &str, date: &DateTime<FixedOffset>, header: bool) -> std::io::Result<()> {
+    let mut content = fs::read_to_string(filename)?;
+    if let Some(replaced) = FrontMatter::replace_date(&content, date) {
+        content = replaced;
+    }
+
+    if header {
+        let start = content.len() - FrontMatter::parse(&content).1.len();
+        let first = content[start..].lines().next().unwrap_or_default();
+        if header_date(first).is_some() {
+            let prefix = if first.starts_with('#') { "# " } else { "" };
+            content.replace_range(start..start + first.len(), &format!("{}{}", prefix, date.to_rfc2822()));
+        }
+    }
+    fs::write(filename, content)
+}
+
+const MAX_TITLE_CHARS: usize = 80;
+
+/// Title taken from an entry's text: its first line, without any Markdown or Org heading markers
+pub fn content_title(body: &str) -> Option<String> {
+    let line = body.lines().map(|l| l.trim()).find(|l| !l.is_empty())?;
+    // `# Heading` and `* Heading`, but not `#tag` or `*emphasis*`
+    let stripped = line.trim_start_matches(['#', '*']);
+    let title = match stripped.len() < line.len() && stripped.starts_with(' ') {
+        true => stripped.trim(),
+        false => line,
+    };
+    if title.is_empty() {
+        return None;
+    }
+
+    match title.char_indices().nth(MAX_TITLE_CHARS) {
+        Some((end, _)) => Some(format!("{}…", title[..end].trim_end())),
+        None => Some(title.to_string()),
+    }
+}
+
+pub fn read_entry_body(filename: &str) -> String {
+    let content = fs::read_to_string(filename).unwrap_or_default();
+    strip_header(&content).to_string()
 }
 
 pub fn get_files(dir: &str) -> Vec<String> {
@@ -49,6 +173,12 @@ pub fn extract_number(x: &str) -> Option<u32> {
     number_regex.find(x).and_then(|m| m.as_str().parse::<u32>().ok())
 }
 
+/// Number of a default entry, e.g. 12 for `Entry_12.txt`. Custom entries are not numbered.
+pub fn extract_entry_number(filename: &str) -> Option<u32> {
+    let entry_regex = Regex::new(r"^Entry_(\d+)\.\w+$").unwrap();
+    entry_regex.captures(filename).and_then(|c| c[1].parse::<u32>().ok())
+}
+
 fn compare_filenames(a: &str, b: &str) -> std::cmp::Ordering {
     match (extract_number(a), extract_number(b)) {
         (Some(_), None) => std::cmp::Ordering::Greater, // Numbers come after non-numbers
@@ -64,48 +194,53 @@ pub struct Entry {
     pub path: String,
     pub number: Option<u32>,
     pub entry_date: Option<DateTime<FixedOffset>>,
-    pub access_date: Option<DateTime<FixedOffset>>
+    pub access_date: Option<DateTime<FixedOffset>>,
+    pub title: Option<String>,
+    pub status: Option<String>,
+    /// When the file was last written, by the journal or any other program
+    pub modified_date: Option<DateTime<FixedOffset>>,
+    /// Kept at the top of every picker and in the main menu
+    pub pinned: bool,
 }
 
 impl Entry {
     pub fn from_file(directory: &str, filename: &str) -> Self {
         let filepath = format!("{}/{}", directory, filename);
-        let file = fs::File::open(&filepath).expect("Could not open file");
-        let reader = io::BufReader::new(file);
-
-        for line in reader.lines() {
-            let line = line.unwrap();
-            let entry_date = DateTime::parse_from_rfc2822(line.trim()).ok();
-            return Self {
-                name: filename.to_string(),
-                path: filepath,
-                number: extract_number(&filename),
-                entry_date,
-                access_date: None
-            }
-        }
+        let content = fs::read_to_string(&filepath).expect("Could not open file");
+        let (front_matter, body) = FrontMatter::parse(&content);
+        let front_matter = front_matter.unwrap_or_default();
 
+        let entry_date = front_matter.date.or_else(|| body.lines().next().and_then(header_date));
+
+        let modified_date = modified_date(&filepath);
         Self {
             name: filename.to_string(),
             path: filepath,
-            number: extract_number(&filename),
-            entry_date: None,
-            access_date: None
+            number: extract_entry_number(filename),
+            entry_date,
+            access_date: None,
+            title: front_matter.title,
+            status: front_matter.status,
+            modified_date,
+            pinned: false,
         }
     }
 
     pub fn build_from_row(dir_path: &str, row: &Row) -> Result<Self> {
-        let number: Option<u32> = row.get(0).ok();
-        let name: String = row.get(1)?;
-        let entry_date = match row.get::<_, String>(2) {
-            Ok(val) => { DateTime::parse_from_rfc2822(&val.trim()).ok()},
-            Err(_) => None, // Failed to fetch the value from the row
-        };
-
-        let access_date = match row.get::<_, String>(3) {
-            Ok(val) => DateTime::parse_from_rfc2822(&val.trim()).ok(),
-            Err(_) => None
-        };
+        let number: Option<u32> = row.get("number").ok();
+        let name: String = row.get("name")?;
+
+        let entry_date = row.get::<_, String>("entry_date").ok()
+            .and_then(|val| from_db_date(&val, row.get("entry_offset").ok().flatten()));
+        let access_date = row.get::<_, String>("access_date").ok()
+            .and_then(|val| from_db_date(&val, row.get("access_offset").ok().flatten()));
+
+        let title: Option<String> = row.get("title").ok().flatten();
+        let status: Option<String> = row.get("status").ok().flatten();
+        let modified_date = row.get::<_, String>("modified_date").ok()
+            .and_then(|val| from_db_date(&val, row.get("modified_offset").ok().flatten()));
+
+        let pinned = row.get::<_, bool>("pinned").unwrap_or(false);
 
         let path = Path::new(dir_path).join(&name);
         let path = path.to_string_lossy().to_string();
@@ -114,14 +249,18 @@ impl Entry {
             number,
             entry_date,
             access_date,
-            path
+            path,
+            title,
+            status,
+            modified_date,
+            pinned,
         })
     }
 
-    pub fn create_default(number: u32, path_config: &PathConfig) -> Self {
+    pub fn create_default(number: u32, format: Format, path_config: &PathConfig) -> Self {
         let entry_date = chrono::offset::Local::now();
         let access_date = chrono::offset::Local::now();
-        let name = format!("Entry_{}.txt", number);
+        let name = format!("Entry_{}.{}", number, format.extension());
         let path = path_config.get_entry_path(&name);
         
         Self {
@@ -129,21 +268,51 @@ impl Entry {
             number: Some(number),
             entry_date: Some(entry_date.into()),
             access_date: Some(access_date.into()),
-            path
+            path,
+            title: None,
+            status: None,
+            modified_date: None,
+            pinned: false,
         }
     }
 
     pub fn create_custom(path_config: &PathConfig, name: &str) -> Self {
         let entry_date = chrono::offset::Local::now();
         let access_date = chrono::offset::Local::now();
-        let path = path_config.get_entry_path(&name);
+        let path = path_config.get_entry_path(name);
 
         Self {
             name: name.to_string(),
             number: None,
             entry_date: Some(entry_date.into()),
             access_date: Some(access_date.into()),
-            path
+            path,
+            title: None,
+            status: None,
+            modified_date: None,
+            pinned: false,
+        }
+    }
+
+    /// Title derived from the file name, e.g. `Entry_12.txt` becomes `Entry 12`
+    pub fn default_title(&self) -> String {
+        let stem = Path::new(&self.name)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.name.clone());
+        stem.replace('_', " ")
+    }
+
+    pub fn display_title(&self) -> String {
+        self.title.clone().unwrap_or_else(|| self.default_title())
+    }
+
+    pub fn front_matter(&self, tags: Vec<String>) -> FrontMatter {
+        FrontMatter {
+            title: self.title.clone(),
+            date: self.entry_date,
+            tags,
+            status: self.status.clone(),
         }
     }
 
@@ -152,7 +321,8 @@ impl Entry {
             return;
         }
         // Write text to file
-        initialize_file(&self.path);
+        let date = self.entry_date.unwrap_or_else(get_time);
+        write_entry_file(&self.path, Some(&self.front_matter(Vec::new())), &date, "");
     }
 
     pub fn delete_entry(&mut self, path_config: &PathConfig) {
@@ -163,23 +333,33 @@ impl Entry {
         fs::remove_file(&self.path).expect("Could not delete file");
     }
 
-    pub fn entry_string(&self) -> String {
-        if let Some(val) = self.entry_date {
-            return val.to_rfc2822();
-        }
-        "".to_string()
+    pub fn entry_string(&self) -> Option<String> {
+        self.entry_date.as_ref().map(to_db_date)
     }
 
-    pub fn access_string(&self) -> String {
-        if let Some(val) = self.access_date {
-            return val.to_rfc2822();
-        }
-        "".to_string()
+    pub fn entry_offset(&self) -> Option<i32> {
+        self.entry_date.map(|d| d.offset().local_minus_utc())
+    }
+
+    pub fn access_string(&self) -> Option<String> {
+        self.access_date.as_ref().map(to_db_date)
+    }
+
+    pub fn access_offset(&self) -> Option<i32> {
+        self.access_date.map(|d| d.offset().local_minus_utc())
+    }
+
+    pub fn modified_string(&self) -> Option<String> {
+        self.modified_date.as_ref().map(to_db_date)
+    }
+
+    pub fn modified_offset(&self) -> Option<i32> {
+        self.modified_date.map(|d| d.offset().local_minus_utc())
     }
 }
 
 // Function to sort entries by `entry_date`
-pub fn sort_entries_by_date(entries: &mut Vec<Entry>, use_access: bool) {
+pub fn sort_entries_by_date(entries: &mut [Entry], use_access: bool) {
     entries.sort_by(|a, b| {
         let a_entry = if use_access { a.access_date } else { a.entry_date };
         let b_entry = if use_access { b.access_date } else { b.entry_date };
@@ -194,15 +374,10 @@ pub fn sort_entries_by_date(entries: &mut Vec<Entry>, use_access: bool) {
 }
 
 pub fn get_entry(entries: Vec<Entry>, name: &str) -> Option<Entry> {
-    for entry in entries {
-        if name == entry.name {
-            return Some(entry);
-        }
-    }
-    None
+    entries.into_iter().find(|entry| name == entry.name)
 }
 
-pub fn sort_entries_by_number(entries: &mut Vec<Entry>) {
+pub fn sort_entries_by_number(entries: &mut [Entry]) {
     entries.sort_by(|a, b| {
         match (a.number, b.number) {
             (Some(a_num), Some(b_num)) => a_num.cmp(&b_num), // Compare dates if both are present
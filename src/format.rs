@@ -0,0 +1,77 @@
+use std::path::Path;
+
+/// The kinds of file an entry can be, told apart by their extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Markdown,
+    Org,
+    Text,
+}
+
+impl Format {
+    /// Reads a format name or extension such as `md`, `.org` or `text`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().trim_start_matches('.').to_lowercase().as_str() {
+            "md" | "markdown" => Some(Format::Markdown),
+            "org" => Some(Format::Org),
+            "txt" | "text" => Some(Format::Text),
+            _ => None,
+        }
+    }
+
+    /// The format of a file, treating unknown extensions as plain text
+    pub fn from_name(name: &str) -> Self {
+        Path::new(name)
+            .extension()
+            .and_then(|e| Self::parse(&e.to_string_lossy()))
+            .unwrap_or(Format::Text)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Markdown => "md",
+            Format::Org => "org",
+            Format::Text => "txt",
+        }
+    }
+
+    /// A heading `level` deep. Plain text underlines the first two levels.
+    pub fn heading(&self, level: usize, text: &str) -> String {
+        match self {
+            Format::Markdown => format!("{} {}\n", "#".repeat(level), text),
+            Format::Org => format!("{} {}\n", "*".repeat(level), text),
+            Format::Text => match level {
+                1 => format!("{}\n{}\n", text, "=".repeat(text.chars().count())),
+                2 => format!("{}\n{}\n", text, "-".repeat(text.chars().count())),
+                _ => format!("{}\n", text),
+            },
+        }
+    }
+
+    /// Level and text of a heading line, e.g. `(2, "Notes")` for `## Notes` in Markdown
+    fn parse_heading<'a>(&self, line: &'a str) -> Option<(usize, &'a str)> {
+        let marker = match self {
+            Format::Markdown => '#',
+            Format::Org => '*',
+            Format::Text => return None,
+        };
+        let text = line.trim_start_matches(marker);
+        let level = line.len() - text.len();
+        match level > 0 && text.starts_with(' ') {
+            true => Some((level, text.trim())),
+            false => None,
+        }
+    }
+}
+
+/// Rewrites the headings in an entry's `body` from one format to another,
+/// `depth` levels deeper so they nest under the headings around them
+pub fn convert_headings(body: &str, from: Format, to: Format, depth: usize) -> String {
+    body.lines()
+        .map(|line| match from.parse_heading(line) {
+            Some((level, text)) => to.heading(level + depth, text).trim_end().to_string(),
+            None => line.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
@@ -0,0 +1,355 @@
+use chrono::{DateTime, SecondsFormat, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone};
+use crate::format::Format;
+
+/// Metadata block at the top of a file, delimited by `---` (YAML) or `+++` (TOML)
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    pub date: Option<DateTime<FixedOffset>>,
+    pub tags: Vec<String>,
+    pub status: Option<String>,
+}
+
+/// Reads a YAML or TOML value, undoing the escapes `quote` and `quote_toml` add
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    // Inside single quotes the only escape is a doubled quote
+    if let Some(inner) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+        return inner.replace("''", "'");
+    }
+    let inner = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(inner) => inner,
+        None => return value.trim_matches(|c| c == '"' || c == '\'').to_string(),
+    };
+    let mut text = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => text.push('\n'),
+            Some('t') => text.push('\t'),
+            Some(escaped) => text.push(escaped),
+            None => text.push(c),
+        }
+    }
+    text
+}
+
+/// Quotes YAML values that would otherwise be read as something else
+fn quote(value: &str) -> String {
+    let plain = !value.contains([':', '#', '"', '\'', '[', '{']) && value.trim() == value;
+    match plain {
+        true => value.to_string(),
+        // Backslashes would be read as escapes inside double quotes
+        false if value.contains(['"', '\\']) => format!("'{}'", value.replace('\'', "''")),
+        false => format!("\"{}\"", value),
+    }
+}
+
+/// A TOML basic string
+fn quote_toml(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value.trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(unquote)
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+/// Parses the date formats commonly found in front matter, treating dates without an offset as local time
+pub fn parse_date(value: &str) -> Option<DateTime<FixedOffset>> {
+    let value = value.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date);
+    }
+    if let Ok(date) = DateTime::parse_from_rfc2822(value) {
+        return Some(date);
+    }
+    // Org timestamps, e.g. `<2026-03-04 Wed 21:30>`
+    let org;
+    let value = match value.starts_with(['<', '[']) {
+        true => {
+            org = value.trim_matches(['<', '>', '[', ']'])
+                .split_whitespace()
+                .filter(|word| !word.chars().all(|c| c.is_alphabetic()))
+                .collect::<Vec<&str>>()
+                .join(" ");
+            org.as_str()
+        },
+        false => value,
+    };
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().and_then(|d| d.and_hms_opt(0, 0, 0)))?;
+
+    Local.from_local_datetime(&naive).earliest().map(|date| date.into())
+}
+
+impl FrontMatter {
+    /// Splits `content` into its front matter and the remaining body.
+    /// Content without a front matter block is returned unchanged as the body.
+    pub fn parse(content: &str) -> (Option<Self>, &str) {
+        if content.starts_with("#+") {
+            return Self::parse_org(content);
+        }
+        let delimiter = match content.lines().next().map(|l| l.trim_end()) {
+            Some("---") => "---",
+            Some("+++") => "+++",
+            _ => return (None, content),
+        };
+
+        let start = content.find('\n').map(|i| i + 1).unwrap_or(content.len());
+        let mut offset = start;
+        let mut end = None;
+        for line in content[start..].split_inclusive('\n') {
+            if line.trim_end() == delimiter {
+                end = Some((offset, offset + line.len()));
+                break;
+            }
+            offset += line.len();
+        }
+        let (block_end, body_start) = match end {
+            Some(end) => end,
+            None => return (None, content),
+        };
+
+        let mut matter = Self::default();
+        let mut list_key: Option<String> = None;
+        for line in content[start..block_end].lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            // YAML block lists, e.g. tags spread over several `- tag` lines
+            if let (Some(key), Some(item)) = (&list_key, trimmed.strip_prefix("- ")) {
+                if key == "tags" {
+                    matter.tags.push(unquote(item));
+                }
+                continue;
+            }
+
+            let (key, value) = match trimmed.split_once([':', '=']) {
+                Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+                None => continue,
+            };
+            list_key = if value.is_empty() { Some(key.clone()) } else { None };
+
+            match key.as_str() {
+                "title" => matter.title = Some(unquote(value)),
+                "date" | "created" => matter.date = parse_date(&unquote(value)),
+                "tags" | "keywords" if !value.is_empty() => matter.tags = parse_list(value),
+                "status" => matter.status = Some(unquote(value)),
+                _ => {}
+            }
+        }
+        (Some(matter), &content[body_start..])
+    }
+
+    /// Org files keep their metadata in `#+KEY: value` lines at the top
+    fn parse_org(content: &str) -> (Option<Self>, &str) {
+        let mut matter = Self::default();
+        let mut end = 0;
+        for line in content.split_inclusive('\n') {
+            let keyword = match line.trim().strip_prefix("#+") {
+                Some(keyword) => keyword,
+                None => break,
+            };
+            end += line.len();
+
+            let (key, value) = match keyword.split_once(':') {
+                Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+                None => continue,
+            };
+            match key.as_str() {
+                "title" => matter.title = Some(value.to_string()),
+                "date" => matter.date = parse_date(value),
+                "filetags" | "tags" => {
+                    matter.tags = value.split([':', ' ', ',']).filter(|t| !t.is_empty()).map(String::from).collect();
+                },
+                "status" => matter.status = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        (Some(matter), &content[end..])
+    }
+
+    /// Renders the front matter as Org keyword lines
+    pub fn render_org(&self) -> String {
+        let mut text = String::new();
+        if let Some(title) = &self.title {
+            text.push_str(&format!("#+TITLE: {}\n", title));
+        }
+        if let Some(date) = &self.date {
+            text.push_str(&format!("#+DATE: {}\n", org_timestamp(date)));
+        }
+        if !self.tags.is_empty() {
+            text.push_str(&format!("#+FILETAGS: :{}:\n", self.tags.join(":")));
+        }
+        if let Some(status) = &self.status {
+            text.push_str(&format!("#+STATUS: {}\n", status));
+        }
+        text
+    }
+
+    /// Renders the front matter as a YAML block
+    pub fn render(&self) -> String {
+        let mut text = String::from("---\n");
+        if let Some(title) = &self.title {
+            text.push_str(&format!("title: {}\n", quote(title)));
+        }
+        if let Some(date) = &self.date {
+            text.push_str(&format!("date: {}\n", date.to_rfc3339_opts(SecondsFormat::Secs, false)));
+        }
+        text.push_str(&format!("tags: [{}]\n", self.tags.join(", ")));
+        if let Some(status) = &self.status {
+            text.push_str(&format!("status: {}\n", quote(status)));
+        }
+        text.push_str("---\n");
+        text
+    }
+
+    /// Rewrites the `date` line of the front matter block in `content`, keeping every other line as written.
+    /// Returns `None` when there is no front matter date to replace.
+    pub fn replace_date(content: &str, date: &DateTime<FixedOffset>) -> Option<String> {
+        if content.starts_with("#+") {
+            let value = org_timestamp(date);
+            return Self::set_org_value(content, "date", Some(&value), false);
+        }
+        let delimiter = match content.lines().next().map(|l| l.trim_end()) {
+            Some(delimiter @ ("---" | "+++")) => delimiter,
+            _ => return None,
+        };
+
+        let date = date.to_rfc3339_opts(SecondsFormat::Secs, false);
+        let mut replaced = false;
+        let mut text = String::new();
+        let mut lines = content.split_inclusive('\n');
+        text.push_str(lines.next()?);
+        for line in lines.by_ref() {
+            if line.trim_end() == delimiter {
+                text.push_str(line);
+                break;
+            }
+            let (key, separator) = match line.find([':', '=']) {
+                Some(i) => (&line[..i], &line[i..i + 1]),
+                None => ("", ""),
+            };
+            let is_date = ["date", "created"].contains(&key.trim().to_lowercase().as_str());
+            if !is_date || replaced {
+                text.push_str(line);
+                continue;
+            }
+            // TOML values are quoted, YAML ones are not
+            match separator {
+                "=" => text.push_str(&format!("{} = \"{}\"\n", key.trim_end(), date)),
+                _ => text.push_str(&format!("{}: {}\n", key.trim_end(), date)),
+            }
+            replaced = true;
+        }
+        if !replaced {
+            return None;
+        }
+        text.extend(lines);
+        Some(text)
+    }
+
+    /// Sets `key` in the front matter of `content`, replacing its line or adding one,
+    /// and starting a YAML block if there is none. `None` removes the key.
+    pub fn set_value(content: &str, key: &str, value: Option<&str>, format: Format) -> String {
+        if content.starts_with("#+") || (format == Format::Org && Self::parse(content).0.is_none()) {
+            return Self::set_org_value(content, key, value, true).unwrap_or_else(|| content.to_string());
+        }
+        let toml = content.lines().next().map(|l| l.trim_end()) == Some("+++");
+        let line = value.map(|value| match toml {
+            true => format!("{} = {}\n", key, quote_toml(value)),
+            false => format!("{}: {}\n", key, quote(value)),
+        });
+
+        Self::set_line(content, key, line)
+    }
+
+    /// Replaces the tags in the front matter of `content` with `tags`
+    pub fn set_tags(content: &str, tags: &[String], format: Format) -> String {
+        if content.starts_with("#+") || (format == Format::Org && Self::parse(content).0.is_none()) {
+            let content = Self::set_org_value(content, "tags", None, false).unwrap_or_else(|| content.to_string());
+            let value = Some(format!(":{}:", tags.join(":"))).filter(|_| !tags.is_empty());
+            return Self::set_org_value(&content, "filetags", value.as_deref(), true).unwrap_or(content);
+        }
+        let line = match content.lines().next().map(|l| l.trim_end()) == Some("+++") {
+            true => format!("tags = [{}]\n", tags.iter().map(|t| format!("\"{}\"", t)).collect::<Vec<String>>().join(", ")),
+            false => format!("tags: [{}]\n", tags.join(", ")),
+        };
+        Self::set_line(content, "tags", Some(line))
+    }
+
+    /// Puts `line` in place of the `key` line of a YAML or TOML block, along with any `- item`
+    /// lines of a block list under it. `None` removes the key.
+    fn set_line(content: &str, key: &str, line: Option<String>) -> String {
+        let (front_matter, body) = Self::parse(content);
+        if front_matter.is_none() {
+            return match line {
+                Some(line) => format!("---\n{}---\n{}", line, content),
+                None => content.to_string(),
+            };
+        }
+
+        let block = &content[..content.len() - body.len()];
+        let mut lines = block.split_inclusive('\n').collect::<Vec<&str>>();
+        let position = lines.iter().position(|l| {
+            l.split_once([':', '=']).is_some_and(|(k, _)| k.trim().eq_ignore_ascii_case(key))
+        });
+        if let Some(i) = position {
+            while lines.get(i + 1).is_some_and(|l| l.trim_start().starts_with("- ")) {
+                lines.remove(i + 1);
+            }
+        }
+        match (position, &line) {
+            (Some(i), Some(line)) => lines[i] = line,
+            (Some(i), None) => {
+                lines.remove(i);
+            },
+            // Right after the opening delimiter
+            (None, Some(line)) => lines.insert(1, line),
+            (None, None) => {},
+        }
+        // Nothing but the delimiters left
+        if lines.len() <= 2 {
+            return body.to_string();
+        }
+        format!("{}{}", lines.concat(), body)
+    }
+
+    /// `set_value` for Org keyword lines. Returns `None` if the key is missing and `add` is false.
+    fn set_org_value(content: &str, key: &str, value: Option<&str>, add: bool) -> Option<String> {
+        let body = Self::parse(content).1;
+        let block = &content[..content.len() - body.len()];
+        let mut lines = block.split_inclusive('\n').map(String::from).collect::<Vec<String>>();
+        let line = value.map(|value| format!("#+{}: {}\n", key.to_uppercase(), value));
+
+        let position = lines.iter().position(|l| {
+            l.trim().strip_prefix("#+").and_then(|k| k.split_once(':')).is_some_and(|(k, _)| k.trim().eq_ignore_ascii_case(key))
+        });
+        match (position, line) {
+            (Some(i), Some(line)) => lines[i] = line,
+            (Some(i), None) => {
+                lines.remove(i);
+            },
+            (None, Some(line)) if add => lines.insert(0, line),
+            _ => return None,
+        }
+        Some(format!("{}{}", lines.concat(), body))
+    }
+}
+
+fn org_timestamp(date: &DateTime<FixedOffset>) -> String {
+    date.format("<%Y-%m-%d %a %H:%M>").to_string()
+}
@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use crate::database::PathConfig;
+
+const GITIGNORE: &str = "db.sqlite\ndb.sqlite-journal\ndb.sqlite-wal\ndb.sqlite-shm\nbackups/\njournal.lock\n.locks/\njournal.*.tmp\n";
+
+fn git(config: &PathConfig, args: &[&str]) -> Output {
+    Command::new("git")
+        .arg("-C")
+        .arg(&config.main_dir)
+        .args(args)
+        .output()
+        .expect("Failed to run git")
+}
+
+fn report(output: &Output) -> bool {
+    if !output.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    output.status.success()
+}
+
+/// Git mode is on once the journal directory is a repository
+pub fn is_enabled(config: &PathConfig) -> bool {
+    Path::new(&config.main_dir).join(".git").exists()
+}
+
+/// Turns the journal directory into a repository and commits the current entries.
+/// The database is ignored since it can be rebuilt from the entries.
+pub fn init(config: &PathConfig) -> bool {
+    if !is_enabled(config) && !report(&git(config, &["init", "-q"])) {
+        return false;
+    }
+
+    commit(config, "Initialize journal");
+    true
+}
+
+/// Adds any of the files in `GITIGNORE` missing from the journal's .gitignore,
+/// so journals set up by older versions don't pick up newer runtime files
+fn update_gitignore(config: &PathConfig) {
+    let gitignore = Path::new(&config.main_dir).join(".gitignore");
+    let current = fs::read_to_string(&gitignore).unwrap_or_default();
+    let missing = GITIGNORE.lines()
+        .filter(|line| !current.lines().any(|l| l.trim() == *line))
+        .collect::<Vec<&str>>();
+    if missing.is_empty() {
+        return;
+    }
+
+    let mut text = current;
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text.push_str(&format!("{}\n", missing.join("\n")));
+    fs::write(gitignore, text).expect("Could not write .gitignore");
+}
+
+/// Commits every change in the journal directory, doing nothing when git mode is off or nothing changed
+pub fn commit(config: &PathConfig, message: &str) {
+    if !is_enabled(config) {
+        return;
+    }
+    update_gitignore(config);
+
+    let status = git(config, &["status", "--porcelain"]);
+    if status.stdout.is_empty() {
+        return;
+    }
+    if report(&git(config, &["add", "-A"])) {
+        report(&git(config, &["commit", "-q", "-m", message]));
+    }
+}
+
+pub fn log(config: &PathConfig, count: usize) -> String {
+    let count = format!("-{}", count);
+    let output = git(config, &["log", &count, "--date=format:%Y-%m-%d %H:%M", "--pretty=format:%h  %ad  %s"]);
+    report(&output);
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+/// Sets the remote used by `push` and `pull`
+pub fn set_remote(config: &PathConfig, url: &str) -> bool {
+    let exists = git(config, &["remote", "get-url", "origin"]).status.success();
+    if exists {
+        report(&git(config, &["remote", "set-url", "origin", url]))
+    } else {
+        report(&git(config, &["remote", "add", "origin", url]))
+    }
+}
+
+pub fn push(config: &PathConfig) -> bool {
+    report(&git(config, &["push", "-q", "-u", "origin", "HEAD"]))
+}
+
+pub fn pull(config: &PathConfig) -> bool {
+    let branch = git(config, &["rev-parse", "--abbrev-ref", "HEAD"]);
+    let branch = String::from_utf8_lossy(&branch.stdout).trim().to_string();
+    report(&git(config, &["pull", "-q", "--no-rebase", "origin", &branch]))
+}
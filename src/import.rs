@@ -0,0 +1,297 @@
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone};
+use regex::Regex;
+use serde_json::Value;
+use crate::database::EntryDB;
+use crate::file_operations::{file_exists, get_entry, write_entry_file, Entry};
+use crate::format::Format;
+use crate::front_matter::FrontMatter;
+use crate::names::{suggest_name, validate_name};
+
+/// An entry read from another journaling tool, before it is written into the journal.
+/// `body` holds the full text, `title` is only used to name the file.
+#[derive(Debug)]
+pub struct ImportedEntry {
+    pub title: String,
+    pub date: DateTime<FixedOffset>,
+    pub body: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: Vec<String>,
+    pub duplicates: Vec<String>,
+}
+
+fn slugify(title: &str) -> String {
+    let slug = title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join("_");
+    slug.chars().take(48).collect()
+}
+
+impl ImportedEntry {
+    pub fn file_name(&self, format: Format) -> String {
+        let stamp = self.date.format("%Y-%m-%d_%H%M");
+        let slug = slugify(&self.title);
+        if slug.is_empty() {
+            format!("{}.{}", stamp, format.extension())
+        } else {
+            format!("{}_{}.{}", stamp, slug, format.extension())
+        }
+    }
+}
+
+/// Imported names come from titles and paths, so one that isn't a valid entry name is replaced with a safe one
+fn entry_name(name: &str) -> String {
+    validate_name(name, None).unwrap_or_else(|_| suggest_name(name, None))
+}
+
+fn local_date(naive: NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+    Local.from_local_datetime(&naive).earliest().map(|dt| dt.into())
+}
+
+fn inline_tags(text: &str) -> Vec<String> {
+    let tag_regex = Regex::new(r"(?:^|\s)@([\w-]+)").unwrap();
+    let mut tags = tag_regex
+        .captures_iter(text)
+        .map(|c| c[1].to_lowercase())
+        .collect::<Vec<String>>();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+/// Parses jrnl's plain text format, where each entry starts with a `[YYYY-MM-DD HH:MM] Title` line
+pub fn parse_jrnl_text(content: &str) -> Vec<ImportedEntry> {
+    let header = Regex::new(r"^\[?(\d{4}-\d{2}-\d{2} \d{1,2}:\d{2}(?: ?[AaPp][Mm])?)\]? ?(.*)$").unwrap();
+    let mut entries: Vec<ImportedEntry> = Vec::new();
+
+    for line in content.lines() {
+        let parsed = header.captures(line).and_then(|c| {
+            let stamp = c[1].to_uppercase();
+            NaiveDateTime::parse_from_str(&stamp, "%Y-%m-%d %H:%M")
+                .or_else(|_| NaiveDateTime::parse_from_str(&stamp, "%Y-%m-%d %I:%M %p"))
+                .or_else(|_| NaiveDateTime::parse_from_str(&stamp, "%Y-%m-%d %I:%M%p"))
+                .ok()
+                .and_then(local_date)
+                .map(|date| (date, c[2].trim().to_string()))
+        });
+
+        match (parsed, entries.last_mut()) {
+            (Some((date, title)), _) => entries.push(ImportedEntry {
+                body: format!("{}\n", title),
+                title,
+                date,
+                tags: Vec::new(),
+            }),
+            (None, Some(entry)) => {
+                entry.body.push_str(line);
+                entry.body.push('\n');
+            },
+            (None, None) => {},
+        }
+    }
+
+    for entry in entries.iter_mut() {
+        entry.body = entry.body.trim().to_string();
+        entry.tags = inline_tags(&entry.body);
+    }
+    entries
+}
+
+/// Parses the output of `jrnl --export json`
+pub fn parse_jrnl_json(content: &str) -> Vec<ImportedEntry> {
+    let json: Value = serde_json::from_str(content).expect("Could not parse jrnl JSON export");
+    let entries = json["entries"].as_array().cloned().unwrap_or_default();
+
+    entries.iter().filter_map(|e| {
+        let stamp = format!("{} {}", e["date"].as_str()?, e["time"].as_str().unwrap_or("00:00"));
+        let date = NaiveDateTime::parse_from_str(&stamp, "%Y-%m-%d %H:%M").ok().and_then(local_date)?;
+        let title = e["title"].as_str().unwrap_or_default().trim().to_string();
+        let body = format!("{}\n{}", title, e["body"].as_str().unwrap_or_default()).trim().to_string();
+        let mut tags = e["tags"].as_array()
+            .map(|tags| tags.iter()
+                .filter_map(|t| t.as_str())
+                .map(|t| t.trim_start_matches('@').to_lowercase())
+                .collect::<Vec<String>>())
+            .unwrap_or_else(|| inline_tags(&body));
+        tags.sort();
+        tags.dedup();
+
+        Some(ImportedEntry { title, date, body, tags })
+    }).collect()
+}
+
+pub fn parse_jrnl(path: &str) -> Vec<ImportedEntry> {
+    let content = fs::read_to_string(path).expect("Could not read jrnl export");
+    if content.trim_start().starts_with('{') {
+        parse_jrnl_json(&content)
+    } else {
+        parse_jrnl_text(&content)
+    }
+}
+
+fn dayone_entries(json: &Value) -> Vec<ImportedEntry> {
+    let entries = json["entries"].as_array().cloned().unwrap_or_default();
+    // Day One escapes Markdown punctuation in its exports, other backslashes are kept
+    let escape = Regex::new(r"\\([!-/:-@\[-`{-~])").unwrap();
+
+    entries.iter().filter_map(|e| {
+        let date = DateTime::parse_from_rfc3339(e["creationDate"].as_str()?).ok()?;
+        let text = escape.replace_all(e["text"].as_str().unwrap_or_default(), "$1");
+        let body = text.trim();
+        // Day One has no title field, so the first line of the text is used as the title
+        let title = body.lines().next().unwrap_or_default().trim_start_matches('#').trim();
+        let tags = e["tags"].as_array()
+            .map(|tags| tags.iter()
+                .filter_map(|t| t.as_str())
+                .map(|t| t.to_lowercase())
+                .collect::<Vec<String>>())
+            .unwrap_or_default();
+
+        Some(ImportedEntry {
+            title: title.to_string(),
+            date,
+            body: body.to_string(),
+            tags,
+        })
+    }).collect()
+}
+
+/// Parses a Day One JSON export, either the zip archive or an extracted journal file
+pub fn parse_dayone(path: &str) -> Vec<ImportedEntry> {
+    let is_json = Path::new(path).extension().and_then(|e| e.to_str()) == Some("json");
+    if is_json {
+        let content = fs::read_to_string(path).expect("Could not read Day One export");
+        let json: Value = serde_json::from_str(&content).expect("Could not parse Day One export");
+        return dayone_entries(&json);
+    }
+
+    let file = fs::File::open(path).expect("Could not open Day One export");
+    let mut archive = zip::ZipArchive::new(file).expect("Day One export is not a zip archive");
+    let mut entries = Vec::new();
+
+    for i in 0..archive.len() {
+        let mut journal = archive.by_index(i).expect("Could not read Day One archive");
+        if !journal.name().ends_with(".json") {
+            continue;
+        }
+        let mut content = String::new();
+        journal.read_to_string(&mut content).expect("Could not read journal in Day One archive");
+        let json: Value = serde_json::from_str(&content).expect("Could not parse Day One export");
+        entries.extend(dayone_entries(&json));
+    }
+    entries
+}
+
+/// Writes imported entries into the journal, skipping any whose name is already taken
+pub fn import_entries(db: &EntryDB, imported: Vec<ImportedEntry>) -> ImportReport {
+    let mut report = ImportReport::default();
+
+    let format = db.default_format();
+    for item in imported {
+        let name = entry_name(&item.file_name(format));
+        let path = db.config.get_entry_path(&name);
+        if file_exists(&path) || get_entry(db.get_entries(), &name).is_some() {
+            report.duplicates.push(name);
+            continue;
+        }
+
+        let mut entry = Entry::create_custom(&db.config, &name);
+        entry.entry_date = Some(item.date);
+        entry.access_date = None;
+
+        if !item.title.is_empty() {
+            entry.title = Some(item.title.clone());
+        }
+        let front_matter = entry.front_matter(item.tags.clone());
+        write_entry_file(&entry.path, Some(&front_matter), &item.date, &format!("{}\n", item.body));
+        db.add_entry_to_db(&entry);
+        for tag in item.tags.iter() {
+            db.assign_tag(&entry, tag).expect("Could not assign tag");
+        }
+        report.imported.push(name);
+    }
+    report
+}
+
+fn collect_notes(dir: &Path, notes: &mut Vec<std::path::PathBuf>) {
+    let items = fs::read_dir(dir).expect("Could not read import directory");
+    for item in items.filter_map(|i| i.ok()) {
+        let path = item.path();
+        if path.is_dir() {
+            // Symlinked folders are skipped, a link back up the tree would otherwise be followed forever
+            if !item.file_type().map(|t| t.is_symlink()).unwrap_or(false) {
+                collect_notes(&path, notes);
+            }
+        } else if matches!(path.extension().and_then(|e| e.to_str()), Some("md") | Some("txt")) {
+            notes.push(path);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn link_file(source: &Path, target: &str) {
+    let source = fs::canonicalize(source).expect("Could not resolve note path");
+    std::os::unix::fs::symlink(source, target).expect("Could not link note into journal");
+}
+
+#[cfg(not(unix))]
+fn link_file(source: &Path, target: &str) {
+    fs::copy(source, target).expect("Could not copy note into journal");
+}
+
+/// Imports every `.md` and `.txt` file below `dir`, reading the entry date and tags from front matter
+/// and falling back to the file's modification time. Files are copied unless `link` is set.
+pub fn import_dir(db: &EntryDB, dir: &str, link: bool) -> ImportReport {
+    let mut notes = Vec::new();
+    collect_notes(Path::new(dir), &mut notes);
+    notes.sort();
+
+    let mut report = ImportReport::default();
+    for note in notes {
+        // Notes in subfolders are named after their path, so `a/todo.md` and `b/todo.md` become `a_todo.md` and `b_todo.md`
+        let name = note.strip_prefix(dir).unwrap_or(&note)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<String>>()
+            .join("_");
+        let name = entry_name(&name);
+        let path = db.config.get_entry_path(&name);
+        if file_exists(&path) || get_entry(db.get_entries(), &name).is_some() {
+            report.duplicates.push(note.to_string_lossy().to_string());
+            continue;
+        }
+
+        let content = fs::read_to_string(&note).unwrap_or_default();
+        let matter = FrontMatter::parse(&content).0.unwrap_or_default();
+        let modified = fs::metadata(&note)
+            .and_then(|m| m.modified())
+            .ok()
+            .map(|time| DateTime::<Local>::from(time).into());
+
+        let mut entry = Entry::create_custom(&db.config, &name);
+        entry.entry_date = matter.date.or(modified);
+        entry.access_date = None;
+        entry.title = matter.title.clone();
+        entry.status = matter.status.clone();
+
+        if link {
+            link_file(&note, &entry.path);
+        } else {
+            fs::copy(&note, &entry.path).expect("Could not copy note into journal");
+        }
+        db.add_entry_to_db(&entry);
+        for tag in matter.tags.iter() {
+            db.assign_tag(&entry, &tag.to_lowercase()).expect("Could not assign tag");
+        }
+        report.imported.push(name);
+    }
+    report
+}
@@ -0,0 +1,48 @@
+use std::path::Path;
+use regex::{Captures, Regex};
+use crate::file_operations::Entry;
+
+fn link_regex() -> Regex {
+    Regex::new(r"\[\[([^\[\]|]+)(\|[^\[\]]*)?\]\]").unwrap()
+}
+
+/// Targets of the `[[Entry Name]]` and `[[Entry Name|label]]` links in `text`
+pub fn extract_links(text: &str) -> Vec<String> {
+    let mut targets = link_regex()
+        .captures_iter(text)
+        .map(|c| c[1].trim().to_string())
+        .collect::<Vec<String>>();
+    targets.sort();
+    targets.dedup();
+    targets
+}
+
+fn stem(name: &str) -> String {
+    Path::new(name)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// A link target may name an entry by file name, file name without extension or title
+pub fn link_matches(target: &str, entry: &Entry) -> bool {
+    target == entry.name
+        || target == stem(&entry.name)
+        || entry.title.as_deref().is_some_and(|title| title == target)
+}
+
+/// Points links to `old_name` at `new_name`, keeping whether the extension was written and any label
+pub fn rewrite_links(text: &str, old_name: &str, new_name: &str) -> String {
+    let old_stem = stem(old_name);
+    link_regex().replace_all(text, |c: &Captures| {
+        let target = c[1].trim();
+        let label = c.get(2).map(|m| m.as_str()).unwrap_or_default();
+        if target == old_name {
+            format!("[[{}{}]]", new_name, label)
+        } else if target == old_stem {
+            format!("[[{}{}]]", stem(new_name), label)
+        } else {
+            c[0].to_string()
+        }
+    }).to_string()
+}
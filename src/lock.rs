@@ -0,0 +1,108 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process;
+use crate::database::PathConfig;
+
+const JOURNAL_LOCK: &str = "journal.lock";
+const LOCK_DIR: &str = ".locks";
+
+/// A lock file holding the id of the process that took it, removed when dropped
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+fn owner(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse::<u32>().ok()
+}
+
+fn is_running(pid: u32) -> bool {
+    // Without /proc there is no cheap way to tell, so assume the owner is still alive
+    let proc = Path::new("/proc");
+    !proc.exists() || proc.join(pid.to_string()).exists()
+}
+
+/// Creates the lock file at `path`, or returns the id of the running process holding it.
+/// Locks left behind by processes that are no longer running are taken over.
+fn acquire(path: PathBuf) -> Result<Lock, u32> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).expect("Could not create lock directory");
+    }
+
+    // The id is written to a private file first and then linked into place,
+    // so other processes never see a lock file without an owner
+    let staged = path.with_extension(format!("{}.tmp", process::id()));
+    fs::write(&staged, process::id().to_string()).expect("Could not write lock file");
+
+    let result = loop {
+        match fs::hard_link(&staged, &path) {
+            Ok(_) => break Ok(Lock { path }),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => match owner(&path) {
+                Some(pid) if is_running(pid) => break Err(pid),
+                // A stale lock is replaced in one step by renaming over it. Another session taking it over
+                // at the same time may rename its file over ours, so the owner is read back before it counts.
+                _ => {
+                    fs::rename(&staged, &path).expect("Could not take over lock file");
+                    if owner(&path) == Some(process::id()) {
+                        break Ok(Lock { path });
+                    }
+                    fs::write(&staged, process::id().to_string()).expect("Could not write lock file");
+                },
+            },
+            Err(e) => panic!("Could not create lock file: {}", e),
+        }
+    };
+    fs::remove_file(&staged).ok();
+    result
+}
+
+fn held_by_other(path: &Path) -> bool {
+    owner(path).is_some_and(|pid| pid != process::id() && is_running(pid))
+}
+
+fn entry_lock_path(config: &PathConfig, name: &str) -> PathBuf {
+    Path::new(&config.main_dir).join(LOCK_DIR).join(format!("{}.lock", name))
+}
+
+fn journal_lock_path(config: &PathConfig) -> PathBuf {
+    Path::new(&config.main_dir).join(JOURNAL_LOCK)
+}
+
+/// Taken while an entry is open in the editor
+pub fn lock_entry(config: &PathConfig, name: &str) -> Result<Lock, u32> {
+    acquire(entry_lock_path(config, name))
+}
+
+/// Taken by commands that rewrite the whole journal, such as restore or rebuilding the database
+pub fn lock_journal(config: &PathConfig) -> Result<Lock, u32> {
+    acquire(journal_lock_path(config))
+}
+
+pub fn entry_locked(config: &PathConfig, name: &str) -> bool {
+    held_by_other(&entry_lock_path(config, name))
+}
+
+pub fn journal_locked(config: &PathConfig) -> bool {
+    held_by_other(&journal_lock_path(config))
+}
+
+/// Names of the entries currently open in other sessions
+pub fn locked_entries(config: &PathConfig) -> Vec<String> {
+    let dir = Path::new(&config.main_dir).join(LOCK_DIR);
+    let mut names = fs::read_dir(dir)
+        .map(|items| items
+            .filter_map(|i| i.ok())
+            .filter(|i| held_by_other(&i.path()))
+            .filter_map(|i| i.file_name().to_string_lossy().strip_suffix(".lock").map(String::from))
+            .collect::<Vec<String>>())
+        .unwrap_or_default();
+    names.sort();
+    names
+}
@@ -1,36 +1,60 @@
 use std::io::Write;
 use std::{env, io};
+use std::path::Path;
 use std::process::Command;
-use dialoguer::{theme::ColorfulTheme, Select};
+use chrono::Local;
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, MultiSelect, Select};
 
 pub mod file_operations;
 pub mod database;
+pub mod compile;
+pub mod import;
+pub mod front_matter;
+pub mod mentions;
+pub mod links;
+pub mod git;
+pub mod backup;
+pub mod check;
+pub mod dates;
+pub mod lock;
+pub mod watch;
+pub mod names;
+pub mod format;
+pub mod view;
+pub mod undo;
+pub mod sorting;
 
-use file_operations::{file_exists, initialize_file, sort_entries_by_number, sort_entries_by_date, get_entry, Entry};
-use database::{EntryDB, PathConfig};
+use file_operations::{file_exists, sort_entries_by_date, get_entry, Entry};
+use format::Format;
+use database::{DateField, EntryDB, EntryError, PathConfig};
+use dates::{parse_moment, DateFilter};
+use sorting::{ListOrder, GROUPINGS, SORT_KEYS};
 
 //const ENTRY_DIR: &str = "/home/marcuswrrn/Documents/entries";
 const ENTRY_DIR: &str = "/home/marcuswrrn/Documents/entries_test";
 
-fn get_last_accessed(db: &EntryDB) -> Entry {
-    let entries = db.get_entries();
-
-    // Filter out old entries
-    let mut entries = entries.into_iter().filter(|e| e.access_date.is_some()).collect::<Vec<Entry>>();
-
-    sort_entries_by_date(&mut entries, true);
-    
-    entries.into_iter().last().expect("No valid entries found")
-}
+fn open_file(db: &EntryDB, entry: &mut Entry) {
+    if lock::journal_locked(&db.config) {
+        println!("The journal is being restored or rebuilt in another session, try again later");
+        return;
+    }
+    let _lock = match lock::lock_entry(&db.config, &entry.name) {
+        Ok(lock) => lock,
+        Err(_) => {
+            println!("{} is being edited in another session", entry.name);
+            return;
+        }
+    };
+    // Only recorded once the entry is known to open, so a refused open isn't counted
+    db.update_entry_access_date(entry);
 
-fn open_file(filepath: &str) {
-    if !file_exists(filepath) {
+    if !file_exists(&entry.path) {
         println!("Initializing file!");
-        initialize_file(filepath);
+        entry.initialize();
     }
 
     let status = Command::new("vim").
-        arg(filepath)
+        arg(&entry.path)
         .status()
         .expect("Failed to open Vim");
 
@@ -40,48 +64,206 @@ fn open_file(filepath: &str) {
     } else {
         eprintln!("Did not close as expected");
     }
+
+    db.index_entry(entry);
+    git::commit(&db.config, &format!("Edit {}", entry.name));
 }
 
 fn add_entry(db: &EntryDB) {
-    let entry = db.create_default_entry();
-    open_file(&entry.path);
+    let mut entry = db.create_default_entry();
+    open_file(db, &mut entry);
 }
 
-fn edit_entry(db: &EntryDB) {
-    let mut entries = db.get_entries();
-    sort_entries_by_number(&mut entries);
+const FILTER_ITEM: &str = "Filter by Date";
+const ORDER_ITEM: &str = "Sort and Group";
 
-    if entries.len() < 1 {
-        println!("No files to edit");
-        return;
+/// Asks for a date filter such as `since 2026-01-01` or `last 7d`, an empty answer clears the filter
+fn ask_date_filter() -> Option<DateFilter> {
+    loop {
+        let expression: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Filter (e.g. since 2026-01-01, last 7d, accessed yesterday)")
+            .allow_empty(true)
+            .interact_text()
+            .unwrap_or_default();
+        if expression.trim().is_empty() {
+            return None;
+        }
+
+        let words = expression.split_whitespace().map(String::from).collect::<Vec<String>>();
+        match DateFilter::from_words(&words) {
+            Ok(filter) => return Some(filter),
+            Err(e) => println!("{}", e),
+        }
     }
-    
-    let mut filenames = entries.iter().map(|e| e.name.clone()).collect::<Vec<String>>();
+}
+
+/// The entries shown by a picker, narrowed by its date filter if one is set, in the list's order
+fn picker_entries(db: &EntryDB, filter: &mut Option<DateFilter>, order: &ListOrder) -> Vec<Entry> {
+    let mut entries = match filter.map(|f| f.apply(db)) {
+        Some(entries) if !entries.is_empty() => entries,
+        Some(_) => {
+            println!("No entries match the filter");
+            *filter = None;
+            db.get_entries()
+        },
+        None => db.get_entries(),
+    };
+    order.sort(&mut entries);
+    entries
+}
 
-    filenames.push("Exit".to_string());
+/// An entry as shown in pickers: its title, followed by the file name when the two differ
+fn entry_label(entry: &Entry) -> String {
+    match &entry.title {
+        Some(title) if *title != entry.default_title() => format!("{} ({})", title, entry.name),
+        _ => entry.name.clone(),
+    }
+}
+
+/// What a line of a picker stands for
+#[derive(Clone, Copy)]
+enum PickerItem {
+    Header,
+    Entry(usize),
+    Filter,
+    Order,
+    Exit,
+}
+
+/// The lines of a picker over `entries`, under group headers if the order groups them
+fn picker_items(db: &EntryDB, entries: &[Entry], order: &ListOrder, exit: bool) -> (Vec<String>, Vec<PickerItem>) {
+    let locked = lock::locked_entries(&db.config);
+    let mut labels = Vec::new();
+    let mut items = Vec::new();
+
+    // Pinned entries come first, in the list's order, and aren't repeated in the groups below
+    let pinned = (0..entries.len()).filter(|&i| entries[i].pinned).collect::<Vec<usize>>();
+    let mut groups = order.group(db, entries);
+    if !pinned.is_empty() {
+        for (_, indices) in groups.iter_mut() {
+            indices.retain(|i| !entries[*i].pinned);
+        }
+        groups.retain(|(_, indices)| !indices.is_empty());
+        let rest = match groups.first() {
+            Some((group, _)) if group.is_empty() => vec![(String::from("Entries"), groups.remove(0).1)],
+            _ => Vec::new(),
+        };
+        groups.splice(0..0, std::iter::once((String::from("Pinned"), pinned)).chain(rest));
+    }
+
+    for (group, indices) in groups {
+        if !group.is_empty() {
+            labels.push(format!("── {} ──", group));
+            items.push(PickerItem::Header);
+        }
+        for i in indices {
+            labels.push(match locked.contains(&entries[i].name) {
+                true => format!("{} - being edited in another session", entry_label(&entries[i])),
+                false => entry_label(&entries[i]),
+            });
+            items.push(PickerItem::Entry(i));
+        }
+    }
+
+    labels.push(FILTER_ITEM.to_string());
+    items.push(PickerItem::Filter);
+    labels.push(ORDER_ITEM.to_string());
+    items.push(PickerItem::Order);
+    if exit {
+        labels.push("Exit".to_string());
+        items.push(PickerItem::Exit);
+    }
+    (labels, items)
+}
+
+/// Asks how to sort and group a list, starting from its current order
+fn ask_order(current: ListOrder) -> ListOrder {
+    let theme = ColorfulTheme::default();
+    let keys = SORT_KEYS.iter().map(|(_, label)| *label).collect::<Vec<&str>>();
+    let key = Select::with_theme(&theme)
+        .with_prompt("Sort by")
+        .default(SORT_KEYS.iter().position(|(k, _)| *k == current.key).unwrap_or(0))
+        .items(&keys)
+        .interact_opt();
+    let key = match key {
+        Ok(Some(i)) => SORT_KEYS[i].0,
+        _ => return current,
+    };
+
+    let descending = match Select::with_theme(&theme)
+        .with_prompt("Direction")
+        .default(current.descending as usize)
+        .items(&["Ascending", "Descending"])
+        .interact_opt()
+    {
+        Ok(Some(i)) => i == 1,
+        _ => return current,
+    };
+
+    let groupings = GROUPINGS.iter().map(|(_, label)| *label).collect::<Vec<&str>>();
+    let grouping = match Select::with_theme(&theme)
+        .with_prompt("Group by")
+        .default(GROUPINGS.iter().position(|(g, _)| *g == current.grouping).unwrap_or(0))
+        .items(&groupings)
+        .interact_opt()
+    {
+        Ok(Some(i)) => GROUPINGS[i].0,
+        _ => return current,
+    };
+    ListOrder { key, descending, grouping }
+}
 
+/// Shows a picker over the journal's entries until the user exits, calling `action` on each entry picked.
+/// `list` names the picker, so its sort order and grouping are remembered separately from the others.
+/// `empty` is printed instead when there are no entries to pick from.
+fn entry_picker(db: &EntryDB, list: &str, prompt: &str, empty: &str, mut action: impl FnMut(&mut Entry)) {
+    let mut filter = None;
+    let mut order = ListOrder::load(db, list);
     let mut selection = 0;
     loop {
+        let mut entries = picker_entries(db, &mut filter, &order);
+        if entries.is_empty() {
+            println!("{}", empty);
+            return;
+        }
+        let (labels, items) = picker_items(db, &entries, &order, true);
+
         selection = match Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("=============Edit Files=============")
-        .default(selection)
-        .items(&filenames)
-        .interact_opt() {
+            .with_prompt(prompt)
+            .default(selection.min(labels.len() - 1))
+            .items(&labels)
+            .interact_opt()
+        {
             Ok(Some(choice)) => choice,
-            _ => return
+            _ => return,
         };
 
-        if selection == filenames.len() - 1 {
-            return;
+        match items[selection] {
+            PickerItem::Entry(i) => action(&mut entries[i]),
+            PickerItem::Header => {},
+            PickerItem::Filter => {
+                filter = ask_date_filter();
+                selection = 0;
+            },
+            PickerItem::Order => {
+                order = ask_order(order);
+                order.save(db, list);
+                selection = 0;
+            },
+            PickerItem::Exit => return,
         }
-        let entry = &mut entries[selection];
-
-        db.update_entry_access_date(entry);
-        open_file(&entry.path);
     }
-    
 }
 
+fn edit_entry(db: &EntryDB) {
+    entry_picker(db, "edit", "=============Edit Files=============", "No files to edit", |entry| {
+        open_file(db, entry);
+    });
+}
+
+fn read_entry(db: &EntryDB) {
+    entry_picker(db, "read", "=============Read Entry=============", "No files to read", |entry| view::view_entry(entry));
+}
 
 fn change_name(db: &EntryDB, entry: &mut Entry) {
     loop {
@@ -101,90 +283,782 @@ fn change_name(db: &EntryDB, entry: &mut Entry) {
         
         let action = action.trim();
         if action.trim() == "y" || action.trim().is_empty() {
-            db.change_name(entry, &s.trim());
-            return;
+            match db.change_name(entry, s.trim()) {
+                Ok(_) => return,
+                Err(EntryError::InvalidName(e)) => println!("{}, try {}", e, names::suggest_name(s.trim(), None)),
+                Err(e) => println!("Could not rename {}: {}", entry.name, e),
+            }
         }
     }
 }
 
 fn update_entry_name(db: &EntryDB) {
-    let mut entries = db.get_entries();
+    entry_picker(db, "rename", "=============Change Name=============", "No files to rename", |entry| change_name(db, entry));
+}
+
+fn edit_metadata(db: &EntryDB, entry: &mut Entry) {
+    let current = entry.display_title();
+    let title: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("Title of {} (empty to use the first line)", entry.name))
+        .default(current.clone())
+        .allow_empty(true)
+        .interact_text()
+        .unwrap_or_else(|_| current.clone());
+    if title.trim() != current {
+        if let Err(e) = db.set_title(entry, Some(&title)) {
+            println!("Could not retitle {}: {}", entry.name, e);
+        }
+    }
+
+    let current = entry.entry_date.map(|d| d.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default();
+    let date = loop {
+        let answer: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Date of {}", entry.name))
+            .default(current.clone())
+            .interact_text()
+            .unwrap_or_default();
+        if answer.trim() == current {
+            break None;
+        }
+        match parse_moment(&answer) {
+            Some(date) => break Some(date),
+            None => println!("Could not understand the date {:?}", answer),
+        }
+    };
+
+    let current = entry.number.map(|n| n.to_string()).unwrap_or_default();
+    let number = loop {
+        let answer: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Number")
+            .default(current.clone())
+            .allow_empty(true)
+            .interact_text()
+            .unwrap_or_default();
+        if answer.trim().is_empty() || answer.trim() == current {
+            break None;
+        }
+        match answer.trim().parse::<u32>() {
+            Ok(number) => break Some(number),
+            Err(_) => println!("{} is not a number", answer),
+        }
+    };
+
+    if date.is_none() && number.is_none() {
+        return;
+    }
+    let header = date.is_some() && Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt("Also update the date header in the file?")
+        .default(true)
+        .interact()
+        .unwrap_or(false);
+
+    if let Err(e) = db.update_metadata(entry, date, number, header) {
+        println!("Could not update {}: {}", entry.name, e);
+    }
+}
+
+fn update_entry_metadata(db: &EntryDB) {
+    entry_picker(db, "metadata", "=============Edit Metadata=============", "No files to edit", |entry| edit_metadata(db, entry));
+}
+
+/// Lets the user tick several entries. Returns an empty list if they cancel or pick none.
+/// Picks are kept while the list is filtered or re-sorted, including those the filter hides.
+fn pick_entries(db: &EntryDB, list: &str, prompt: &str, empty: &str) -> Vec<Entry> {
+    let mut filter = None;
+    let mut order = ListOrder::load(db, list);
+    let mut picked: Vec<String> = Vec::new();
+    loop {
+        let entries = picker_entries(db, &mut filter, &order);
+        if entries.is_empty() {
+            println!("{}", empty);
+            return Vec::new();
+        }
+        let (labels, items) = picker_items(db, &entries, &order, false);
+        let ticked = items.iter()
+            .map(|item| matches!(item, PickerItem::Entry(i) if picked.contains(&entries[*i].name)))
+            .collect::<Vec<bool>>();
+
+        let chosen = match MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("{} (space to pick, enter to continue, esc to cancel)", prompt))
+            .items(&labels)
+            .defaults(&ticked)
+            .interact_opt()
+        {
+            Ok(Some(chosen)) => chosen.into_iter().map(|i| items[i]).collect::<Vec<PickerItem>>(),
+            _ => return Vec::new(),
+        };
+
+        // Listed entries are picked as ticked now, hidden ones stay as they were.
+        // With tag groups an entry can be listed, and ticked, more than once.
+        picked.retain(|name| !entries.iter().any(|e| e.name == *name));
+        for item in chosen.iter() {
+            if let PickerItem::Entry(i) = item {
+                if !picked.contains(&entries[*i].name) {
+                    picked.push(entries[*i].name.clone());
+                }
+            }
+        }
+
+        if chosen.iter().any(|item| matches!(item, PickerItem::Filter)) {
+            filter = ask_date_filter();
+            continue;
+        }
+        if chosen.iter().any(|item| matches!(item, PickerItem::Order)) {
+            order = ask_order(order);
+            order.save(db, list);
+            continue;
+        }
+        let mut entries = db.get_entries();
+        entries.retain(|e| picked.contains(&e.name));
+        order.sort(&mut entries);
+        return entries;
+    }
+}
+
+/// Lists the entries a batch will change and asks before going ahead
+fn confirm_batch(action: &str, entries: &[Entry]) -> bool {
+    println!("{} {} entries:", action, entries.len());
+    for entry in entries {
+        println!("  {}", entry_label(entry));
+    }
+    Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt("Go ahead?")
+        .default(false)
+        .interact()
+        .unwrap_or(false)
+}
+
+fn delete_entry(db: &EntryDB) {
+    let entries = pick_entries(db, "delete", "=============Delete Entries=============", "No files to delete");
+    if entries.is_empty() || !confirm_batch("Deleting", &entries) {
+        return;
+    }
+    let before = db.last_operation_id();
+    for mut entry in entries {
+        match db.delete_entry(&mut entry) {
+            Ok(_) => println!("Deleted {}", entry.name),
+            Err(e) => println!("Could not delete {}: {}", entry.name, e),
+        }
+    }
+    db.group_operations(before);
+}
+
+fn tag_entries(db: &EntryDB) {
+    let entries = pick_entries(db, "tag", "=============Tag Entries=============", "No files to tag");
     if entries.is_empty() {
-        println!("No files to edit");
         return;
     }
+    let answer: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Tags to add, with - in front of those to remove (e.g. draft -todo)")
+        .interact_text()
+        .unwrap_or_default();
+
+    let (mut add, mut remove) = (Vec::new(), Vec::new());
+    for word in answer.split_whitespace().map(|w| w.trim_start_matches('#').to_lowercase()) {
+        match word.strip_prefix('-') {
+            Some(tag) if !tag.is_empty() => remove.push(tag.to_string()),
+            Some(_) => {},
+            None => add.push(word),
+        }
+    }
+    if add.is_empty() && remove.is_empty() {
+        return;
+    }
+
+    let action = match (add.is_empty(), remove.is_empty()) {
+        (false, true) => format!("Adding {} to", add.join(", ")),
+        (true, false) => format!("Removing {} from", remove.join(", ")),
+        _ => format!("Adding {} and removing {} on", add.join(", "), remove.join(", ")),
+    };
+    if !confirm_batch(&action, &entries) {
+        return;
+    }
+    let before = db.last_operation_id();
+    for mut entry in entries {
+        if let Err(e) = db.retag_entry(&mut entry, &add, &remove) {
+            println!("Could not tag {}: {}", entry.name, e);
+        }
+    }
+    db.group_operations(before);
+}
 
-    let mut filenames = entries.iter().map(|e| e.name.clone()).collect::<Vec<String>>();
-    filenames.push("Exit".to_string());
+fn export_entries(db: &EntryDB) {
+    let mut entries = pick_entries(db, "export", "=============Export Entries=============", "No files to export");
+    if entries.is_empty() {
+        return;
+    }
+    sort_entries_by_date(&mut entries, false);
 
+    let default = Path::new(&db.config.main_dir)
+        .join(format!("export.{}", match db.default_format() {
+            Format::Org => "org",
+            _ => "md",
+        }))
+        .to_string_lossy()
+        .to_string();
+    let output: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Export to")
+        .default(default)
+        .interact_text()
+        .unwrap_or_default();
+    if output.trim().is_empty() {
+        return;
+    }
+    let chapters = match Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Export as")
+        .default(0)
+        .items(&["Plain, each entry under its title", "Manuscript with numbered chapters"])
+        .interact_opt()
+    {
+        Ok(Some(choice)) => choice == 1,
+        _ => return,
+    };
+    if !confirm_batch(&format!("Exporting to {}", output.trim()), &entries) {
+        return;
+    }
+    compile::export_entries(&entries, output.trim(), chapters);
+    println!("Exported {} entries to {}", entries.len(), output.trim());
+}
+
+fn backlinks(db: &EntryDB) {
+    entry_picker(db, "backlinks", "=============Backlinks=============", "No files to edit", |entry| {
+        let mut sources = db.get_backlinks(entry);
+        if sources.is_empty() {
+            println!("No entries link to {}", entry.name);
+            return;
+        }
+        sources.push("Back".to_string());
+
+        let choice = match Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("Entries linking to {}", entry.name))
+        .default(0)
+        .items(&sources)
+        .interact_opt() {
+            Ok(Some(choice)) => choice,
+            _ => return
+        };
+
+        if choice == sources.len() - 1 {
+            return;
+        }
+        if let Some(mut source) = get_entry(db.get_entries(), &sources[choice]) {
+            open_file(db, &mut source);
+        }
+    });
+}
+
+fn links_command(args: &[String], db: &EntryDB) {
+    match args.get(2).map(|a| a.as_str()) {
+        Some("--broken") | None => {
+            for (source, target) in db.get_broken_links() {
+                println!("{}: [[{}]]", source, target);
+            }
+        },
+        Some(name) => {
+            let entry = match get_entry(db.get_entries(), name) {
+                Some(entry) => entry,
+                None => {
+                    println!("No entry named {}", name);
+                    return;
+                }
+            };
+            for source in db.get_backlinks(&entry) {
+                println!("{}", source);
+            }
+        }
+    }
+}
+
+fn last_accessed(db: &EntryDB) {
+    let mut entry = match db.get_last_accessed() {
+        Some(entry) => entry,
+        None => {
+            println!("No entries have been opened yet");
+            return;
+        }
+    };
+    
+    open_file(db, &mut entry);
+}
+
+/// Number of entries shown by "Recent" and `journal recent`
+const RECENT_COUNT: usize = 10;
+
+fn recent_entries(db: &EntryDB) {
     let mut selection = 0;
     loop {
+        let mut recent = db.get_recent_entries(RECENT_COUNT);
+        if recent.is_empty() {
+            println!("No entries have been opened yet");
+            return;
+        }
+        let mut items = recent.iter()
+            .map(|(entry, opens)| format!("{} - opened {} {}", entry_label(entry), opens, if *opens == 1 { "time" } else { "times" }))
+            .collect::<Vec<String>>();
+        items.push("Exit".to_string());
+
         selection = match Select::with_theme(&ColorfulTheme::default())
-            .with_prompt("=============Change Name=============")
-            .default(selection)
-            .items(&filenames)
+            .with_prompt("=============Recent=============")
+            .default(selection.min(items.len() - 1))
+            .items(&items)
             .interact_opt()
         {
             Ok(Some(choice)) => choice,
             _ => return,
         };
-        
-        if selection == filenames.len() - 1 {
+        if selection == items.len() - 1 {
+            return;
+        }
+
+        let entry = &mut recent[selection].0;
+        open_file(db, entry);
+        // The entry just opened moves to the top
+        selection = 0;
+    }
+}
+
+fn recent_command(args: &[String], db: &EntryDB) {
+    let count = args.get(2).and_then(|c| c.parse::<usize>().ok()).unwrap_or(RECENT_COUNT);
+    for (entry, opens) in db.get_recent_entries(count) {
+        let date = entry.access_date.map(|d| d.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default();
+        println!("{}  {:>4}  {}  {}", date, opens, entry.name, entry.display_title());
+    }
+}
+
+fn project_command(args: &[String], db: &EntryDB) {
+    let project = match args.get(2) {
+        Some(project) => project,
+        None => {
+            for project in db.get_projects() {
+                println!("{}", project);
+            }
             return;
         }
+    };
 
-        // Ensure we get a fresh mutable reference to the selected entry
-        if let Some(entry) = entries.get_mut(selection) {
-            change_name(db, entry);
-            filenames = entries.iter().map(|e| e.name.clone()).collect::<Vec<String>>();
-            filenames.push(String::from("Exit"));
+    let action = args.get(3).map(|a| a.as_str());
+    if action.is_none() {
+        for (i, entry) in db.get_project_entries(project).iter().enumerate() {
+            println!("{:>3}. {}", i + 1, entry_label(entry));
         }
+        return;
+    }
+
+    let entry = match args.get(4).and_then(|name| get_entry(db.get_entries(), name)) {
+        Some(entry) => entry,
+        None => {
+            println!("Usage: journal project <project> [add|move|remove] <entry> [position]");
+            return;
+        }
+    };
+
+    match action {
+        Some("add") | Some("move") => {
+            let position = args.get(5).and_then(|p| p.parse::<usize>().ok());
+            db.add_to_project(project, &entry, position).expect("Could not add entry to project");
+        },
+        Some("remove") => {
+            db.remove_from_project(project, &entry).expect("Could not remove entry from project");
+        },
+        _ => println!("Unknown project action, expected add, move or remove"),
     }
 }
 
-fn delete_entry(db: &EntryDB) {
-    let mut selection = 0;
-    loop {
-        let mut entries = db.get_entries();
+fn compile_command(args: &[String], db: &EntryDB) {
+    let project = match args.get(2) {
+        Some(project) => project,
+        None => {
+            println!("Usage: journal compile <project> [output]");
+            return;
+        }
+    };
+
+    match compile::compile_project(db, project, args.get(3).map(|o| o.as_str())) {
+        Some(output) => println!("Compiled {} into {}", project, output),
+        None => println!("Project {} has no entries", project),
+    }
+}
 
-        if entries.len() < 1 {
-            println!("No files to edit");
+fn import_command(args: &[String], db: &EntryDB) {
+    let (source, path) = match (args.get(2), args.get(3)) {
+        (Some(source), Some(path)) => (source.as_str(), path.as_str()),
+        _ => {
+            println!("Usage: journal import [jrnl|dayone|dir] <path> [--link]");
             return;
         }
+    };
 
-        let mut filenames = entries.iter().map(|e| e.name.clone()).collect::<Vec<String>>();
-        filenames.push("Exit".to_string());
+    let _lock = match lock_journal(db) {
+        Some(lock) => lock,
+        None => return,
+    };
+    let report = match source {
+        "jrnl" => import::import_entries(db, import::parse_jrnl(path)),
+        "dayone" => import::import_entries(db, import::parse_dayone(path)),
+        "dir" => import::import_dir(db, path, args.iter().any(|a| a == "--link")),
+        _ => {
+            println!("Unknown import source {}, expected jrnl, dayone or dir", source);
+            return;
+        }
+    };
 
-        selection = match Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("=============Edit Files=============")
-        .default(selection)
-        .items(&filenames)
-        .interact_opt() {
-            Ok(Some(choice)) => choice,
-            _ => return
+    for name in report.duplicates.iter() {
+        println!("Skipped duplicate: {}", name);
+    }
+    println!("Imported {} entries ({} duplicates)", report.imported.len(), report.duplicates.len());
+    git::commit(&db.config, &format!("Import {} entries from {}", report.imported.len(), source));
+}
+
+fn print_index(index: Vec<(String, Vec<String>)>, filter: Option<&String>, sigil: &str) {
+    for (key, entries) in index {
+        if filter.is_some_and(|f| f.trim_start_matches(sigil).to_lowercase() != key) {
+            continue;
+        }
+        println!("{}{} ({})", sigil, key, entries.len());
+        for entry in entries {
+            println!("    {}", entry);
+        }
+    }
+}
+
+fn git_command(args: &[String], db: &EntryDB) {
+    match (args.get(2).map(|a| a.as_str()), args.get(3)) {
+        (Some("init"), _) => {
+            if git::init(&db.config) {
+                println!("Journal is now tracked by git in {}", db.config.main_dir);
+            }
+        },
+        (Some("remote"), Some(url)) => {
+            git::set_remote(&db.config, url);
+        },
+        _ => println!("Usage: journal git [init|remote <url>]"),
+    }
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1))
+}
+
+fn backup_command(args: &[String], db: &EntryDB) {
+    let out = flag_value(args, "--out").map(|o| o.as_str());
+    let archive = backup::create_backup(db, out);
+    println!("Backed up journal to {}", archive);
+
+    if let Some(keep) = flag_value(args, "--keep").and_then(|k| k.parse::<usize>().ok()) {
+        for removed in backup::rotate_backups(db, keep) {
+            println!("Removed old backup {}", removed);
+        }
+    }
+}
+
+fn restore_command(args: &[String], db: &EntryDB) {
+    let archive = match args.get(2) {
+        Some(archive) => archive,
+        None => {
+            println!("Usage: journal restore <archive>");
+            return;
+        }
+    };
+
+    let _lock = match lock_journal(db) {
+        Some(lock) => lock,
+        None => return,
+    };
+    match backup::restore_backup(db, archive) {
+        Ok(safety_backup) => {
+            println!("Restored journal from {}", archive);
+            println!("The previous journal was backed up to {}", safety_backup);
+            git::commit(&db.config, &format!("Restore from {}", archive));
+        },
+        Err(problems) => {
+            println!("Backup failed verification, nothing was changed:");
+            for problem in problems {
+                println!("    {}", problem);
+            }
+        }
+    }
+}
+
+fn meta_command(args: &[String], db: &EntryDB) {
+    let mut entry = match args.get(2).and_then(|name| get_entry(db.get_entries(), name)) {
+        Some(entry) => entry,
+        None => {
+            println!("Usage: journal meta <entry> [--title <title>] [--date <date>] [--number <n>] [--header]");
+            return;
+        }
+    };
+
+    let date = match flag_value(args, "--date") {
+        Some(value) => match parse_moment(value) {
+            Some(date) => Some(date),
+            None => {
+                println!("Could not understand the date {:?}", value);
+                return;
+            }
+        },
+        None => None,
+    };
+    let number = match flag_value(args, "--number") {
+        Some(value) => match value.parse::<u32>() {
+            Ok(number) => Some(number),
+            Err(_) => {
+                println!("{} is not a number", value);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    if let Some(title) = flag_value(args, "--title") {
+        if let Err(e) = db.set_title(&mut entry, Some(title)) {
+            println!("Could not retitle {}: {}", entry.name, e);
+            return;
+        }
+    }
+    if date.is_some() || number.is_some() {
+        let header = args.iter().any(|a| a == "--header");
+        if let Err(e) = db.update_metadata(&mut entry, date, number, header) {
+            println!("Could not update {}: {}", entry.name, e);
+            return;
+        }
+    }
+
+    println!("name:   {}", entry.name);
+    println!("title:  {}", entry.display_title());
+    println!("date:   {}", entry.entry_date.map(|d| d.to_rfc2822()).unwrap_or_default());
+    println!("number: {}", entry.number.map(|n| n.to_string()).unwrap_or_default());
+}
+
+fn format_command(args: &[String], db: &EntryDB) {
+    match args.get(2) {
+        Some(value) => match format::Format::parse(value) {
+            Some(format) => {
+                db.set_setting("default_format", format.extension());
+                println!("New entries will be .{} files", format.extension());
+            },
+            None => println!("Unknown format {}, expected md, org or txt", value),
+        },
+        None => println!("{}", db.default_format().extension()),
+    }
+}
+
+fn check_command(args: &[String], db: &EntryDB) {
+    let problems = check::check(db);
+    if problems.is_empty() {
+        println!("No problems found");
+        return;
+    }
+    for problem in problems.iter() {
+        println!("[{}] {}", problem.class(), problem);
+    }
+
+    // --repair fixes everything, --repair=dates,orphans only the listed classes
+    let classes = match args.iter().find(|a| a.starts_with("--repair")) {
+        Some(flag) => match flag.split_once('=') {
+            Some((_, classes)) => classes.split(',').collect::<Vec<&str>>(),
+            None => check::PROBLEM_CLASSES.to_vec(),
+        },
+        None => {
+            let repairable = problems.iter().filter(|p| check::PROBLEM_CLASSES.contains(&p.class())).count();
+            match repairable {
+                0 => println!("{} problems found, none can be repaired automatically", problems.len()),
+                _ => println!("{} problems found, run with --repair[={}] to fix {} of them", problems.len(), check::PROBLEM_CLASSES.join(","), repairable),
+            }
+            return;
+        }
+    };
+
+    let _lock = match lock_journal(db) {
+        Some(lock) => lock,
+        None => return,
+    };
+    let repaired = check::repair(db, &problems, &classes);
+    println!("Repaired {} of {} problems", repaired, problems.len());
+    git::commit(&db.config, "Repair journal");
+}
+
+fn list_command(args: &[String], db: &EntryDB) {
+    let filter = match DateFilter::from_words(&args[2..]) {
+        Ok(filter) => filter,
+        Err(e) => {
+            println!("{}", e);
+            println!("Usage: journal list [--since <date>] [--until <date>] [--last 7d|<day>] [--on <date>] [--accessed]");
+            return;
+        }
+    };
+
+    for entry in filter.apply(db) {
+        let date = match filter.field {
+            DateField::Created => entry.entry_date,
+            DateField::Accessed => entry.access_date,
         };
+        let date = date.map(|d| d.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default();
+        println!("{}  {}  {}", date, entry.name, entry.display_title());
+    }
+}
+
+/// An entry named on the command line, with or without the journal's extension
+fn find_entry(db: &EntryDB, name: &str) -> Option<Entry> {
+    let normalized = names::validate_name(name, Some(db.default_format().extension()));
+    get_entry(db.get_entries(), name)
+        .or_else(|| normalized.ok().and_then(|name| get_entry(db.get_entries(), &name)))
+}
 
-        if selection == filenames.len() - 1 {
+fn view_command(args: &[String], db: &EntryDB) {
+    let name = match args.get(2) {
+        Some(name) => name,
+        None => {
+            println!("Usage: journal view <entry>");
             return;
         }
-        let entry = &mut entries[selection];
-        db.delete_entry(entry);
-        selection -= 1;
+    };
+    match find_entry(db, name) {
+        Some(entry) => view::view_entry(&entry),
+        None => println!("No entry called {}", name),
     }
-    
 }
 
-fn last_accessed(db: &EntryDB) {
-    let entry = &mut get_last_accessed(db);
-    
-    db.update_entry_access_date(entry);
-    open_file(&entry.path);
+fn pin_command(args: &[String], db: &EntryDB) {
+    let pinned = args[1] == "pin";
+    if args.len() < 3 {
+        println!("Usage: journal {} <entry>...", args[1]);
+        return;
+    }
+    for name in &args[2..] {
+        match find_entry(db, name) {
+            Some(mut entry) => {
+                db.set_pinned(&mut entry, pinned);
+                println!("{} {}", if pinned { "Pinned" } else { "Unpinned" }, entry.name);
+            },
+            None => println!("No entry called {}", name),
+        }
+    }
+}
+
+fn toggle_pins(db: &EntryDB) {
+    entry_picker(db, "pin", "=============Pin or Unpin=============", "No files to pin", |entry| {
+        let pinned = !entry.pinned;
+        db.set_pinned(entry, pinned);
+    });
+}
+
+fn print_undone(result: Result<Vec<undo::Operation>, (Vec<undo::Operation>, String)>) {
+    let (undone, error) = match result {
+        Ok(undone) => (undone, None),
+        Err((undone, error)) => (undone, Some(error)),
+    };
+    for operation in undone.iter() {
+        println!("Undid: {}", operation);
+    }
+    if let Some(error) = error {
+        println!("{}", error);
+    } else if undone.is_empty() {
+        println!("Nothing to undo");
+    }
 }
 
+fn undo_command(args: &[String], db: &EntryDB) {
+    if args.get(2).map(String::as_str) == Some("--list") {
+        let count = args.get(3).and_then(|c| c.parse::<usize>().ok()).unwrap_or(20);
+        for operation in undo::recent_operations(db, count) {
+            let date = operation.date.map(|d| d.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default();
+            println!("{}  {}", date, operation);
+        }
+        return;
+    }
+    let count = match args.get(2).map(|c| c.parse::<usize>()) {
+        Some(Ok(count)) => count,
+        Some(Err(_)) => {
+            println!("Usage: journal undo [n] | journal undo --list [n]");
+            return;
+        },
+        None => 1,
+    };
+    print_undone(undo::undo(db, count));
+}
 
-fn argument_handling(args: &Vec<String>, db: &EntryDB) {
+fn undo_last(db: &EntryDB) {
+    let operations = undo::recent_operations(db, 1);
+    let prompt = match operations.as_slice() {
+        [] => {
+            println!("Nothing to undo");
+            return;
+        },
+        [operation] => format!("Undo \"{}\"?", operation),
+        [operation, rest @ ..] => format!("Undo \"{}\" and {} more from the same batch?", operation, rest.len()),
+    };
+    let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(true)
+        .interact()
+        .unwrap_or(false);
+    if confirmed {
+        print_undone(undo::undo(db, 1));
+    }
+}
+
+/// Takes the journal lock for commands that rewrite the whole journal, refusing while entries are open elsewhere
+fn lock_journal(db: &EntryDB) -> Option<lock::Lock> {
+    let journal_lock = match lock::lock_journal(&db.config) {
+        Ok(journal_lock) => journal_lock,
+        Err(pid) => {
+            println!("The journal is locked by another session (process {})", pid);
+            return None;
+        }
+    };
+
+    let open = lock::locked_entries(&db.config);
+    if !open.is_empty() {
+        println!("Close these entries in the other sessions first: {}", open.join(", "));
+        return None;
+    }
+    Some(journal_lock)
+}
+
+fn argument_handling(args: &[String], db: &EntryDB) {
     match args[1].as_str() {
+        "project" => project_command(args, db),
+        "compile" => compile_command(args, db),
+        "import" => import_command(args, db),
+        "tags" => print_index(db.get_tag_index(), args.get(2), "#"),
+        "people" => print_index(db.get_people_index(), args.get(2), "@"),
+        "links" => links_command(args, db),
+        "git" => git_command(args, db),
+        "backup" => backup_command(args, db),
+        "restore" => restore_command(args, db),
+        "check" => check_command(args, db),
+        "list" => list_command(args, db),
+        "meta" => meta_command(args, db),
+        "format" => format_command(args, db),
+        "watch" => watch::watch(db),
+        "view" => view_command(args, db),
+        "undo" => undo_command(args, db),
+        "pin" | "unpin" => pin_command(args, db),
+        "recent" => recent_command(args, db),
+        "pinned" => {
+            for entry in db.get_pinned_entries() {
+                println!("{}  {}", entry.name, entry.display_title());
+            }
+        },
+        "log" => {
+            let count = args.get(2).and_then(|c| c.parse::<usize>().ok()).unwrap_or(20);
+            println!("{}", git::log(&db.config, count));
+        },
+        "push" => {
+            if git::push(&db.config) {
+                println!("Pushed journal");
+            }
+        },
+        "pull" => {
+            if git::pull(&db.config) {
+                println!("Pulled journal");
+            }
+        },
         "--rebuild_db" => {
+            let _lock = match lock_journal(db) {
+                Some(lock) => lock,
+                None => return,
+            };
             println!("Initializing Database!");
             db.rebuild_database();
         },
@@ -193,13 +1067,22 @@ fn argument_handling(args: &Vec<String>, db: &EntryDB) {
             db.init_tables();
         }
         _ => {
-            let entries = db.get_entries();
-            if let Some(mut entry) = get_entry(entries, &args[1]) {
-                db.update_entry_access_date(&mut entry);
-                open_file(&entry.path);
-            } else {
-                let entry = db.create_custom_entry(&args[1]);
-                open_file(&entry.path);
+            // `journal notes` opens notes.txt (or .md, .org) if there is no entry called just notes
+            let extension = db.default_format().extension();
+            let normalized = names::validate_name(&args[1], Some(extension));
+            let existing = get_entry(db.get_entries(), &args[1])
+                .or_else(|| normalized.as_ref().ok().and_then(|name| get_entry(db.get_entries(), name)));
+            if let Some(mut entry) = existing {
+                open_file(db, &mut entry);
+                return;
+            }
+
+            match db.create_custom_entry(&args[1], Some(extension)) {
+                Ok(mut entry) => open_file(db, &mut entry),
+                Err(EntryError::InvalidName(e)) => {
+                    println!("{}, try {}", e, names::suggest_name(&args[1], Some(extension)));
+                },
+                Err(e) => println!("Could not create {}: {}", args[1], e),
             }
         }
     }
@@ -216,34 +1099,71 @@ fn main() {
     }
     println!("Hello World");
     let mut selection = 0; 
-    let options = vec!["Last Accessed", "Add Entry", "Edit Entry", "Delete Entry", "Change Name", "Exit"];  
+    let options = vec!["Last Accessed", "Recent", "Add Entry", "Edit Entry", "Read Entry", "Delete Entries", "Tag Entries", "Export Entries", "Change Name", "Edit Metadata", "Pin Entries", "Backlinks", "Undo Last Action", "Exit"];  
     loop {
-        selection = match Select::with_theme(&ColorfulTheme::default())
+        // Pinned entries open straight from the top of the menu
+        let mut pinned = db.get_pinned_entries();
+        let mut items = pinned.iter().map(|e| format!("Pinned: {}", entry_label(e))).collect::<Vec<String>>();
+        items.extend(options.iter().map(|o| o.to_string()));
+
+        let choice = match Select::with_theme(&ColorfulTheme::default())
             .with_prompt("=============Journal=============")
-            .default(selection)
-            .items(&options)
+            .default(pinned.len() + selection)
+            .items(&items)
             .interact_opt() {
                 Ok(Some(choice)) => choice,
                 _ => return
             };
 
-        match  selection {
+        if choice < pinned.len() {
+            let entry = &mut pinned[choice];
+            open_file(&db, entry);
+            continue;
+        }
+
+        // Kept relative to options so pinning or unpinning doesn't shift it
+        selection = choice - pinned.len();
+        match selection {
             0 => {
                 last_accessed(&db);
             },
             1 => {
-                add_entry(&db);
+                recent_entries(&db);
             },
             2 => {
-                edit_entry(&db);
+                add_entry(&db);
             },
             3 => {
-                delete_entry(&db);
+                edit_entry(&db);
             },
             4 => {
-                update_entry_name(&db);
+                read_entry(&db);
             },
             5 => {
+                delete_entry(&db);
+            },
+            6 => {
+                tag_entries(&db);
+            },
+            7 => {
+                export_entries(&db);
+            },
+            8 => {
+                update_entry_name(&db);
+            },
+            9 => {
+                update_entry_metadata(&db);
+            },
+            10 => {
+                toggle_pins(&db);
+            },
+            11 => {
+                backlinks(&db);
+            },
+            12 => {
+                undo_last(&db);
+            },
+            13 => {
                 return;
             },
             _ => unreachable!(),
@@ -0,0 +1,24 @@
+use regex::Regex;
+
+fn extract(text: &str, sigil: char) -> Vec<String> {
+    // Tokens must start with a letter so `#1` or `@2pm` aren't picked up, and must follow
+    // whitespace so email addresses and URL fragments are skipped
+    let token_regex = Regex::new(&format!(r"(?:^|\s){}(\p{{L}}[\p{{L}}\p{{N}}_-]*)", sigil)).unwrap();
+    let mut tokens = token_regex
+        .captures_iter(text)
+        .map(|c| c[1].trim_end_matches(['-', '_']).to_lowercase())
+        .collect::<Vec<String>>();
+    tokens.sort();
+    tokens.dedup();
+    tokens
+}
+
+/// Inline `#tag` tokens in an entry's text
+pub fn extract_hashtags(text: &str) -> Vec<String> {
+    extract(text, '#')
+}
+
+/// Inline `@person` tokens in an entry's text
+pub fn extract_mentions(text: &str) -> Vec<String> {
+    extract(text, '@')
+}
@@ -0,0 +1,105 @@
+use std::fmt;
+use std::path::Path;
+use unicode_normalization::UnicodeNormalization;
+
+/// Leaves room for the `.locks/<name>.lock` and `.<name>.deleted` files kept next to an entry
+const MAX_NAME_BYTES: usize = 200;
+
+/// Names that can't be used as files on Windows, which matters for journals in sync folders
+const RESERVED_NAMES: [&str; 22] = [
+    "con", "prn", "aux", "nul",
+    "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8", "com9",
+    "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum NameError {
+    Empty,
+    PathSeparator,
+    LeadingDot,
+    ControlCharacter,
+    Reserved(String),
+    TooLong(usize),
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NameError::Empty => write!(f, "The name is empty"),
+            NameError::PathSeparator => write!(f, "Names can't contain / or \\"),
+            NameError::LeadingDot => write!(f, "Names can't start with a dot"),
+            NameError::ControlCharacter => write!(f, "Names can't contain control characters"),
+            NameError::Reserved(name) => write!(f, "{} is a reserved file name", name),
+            NameError::TooLong(length) => write!(f, "The name is {} bytes long, the limit is {}", length, MAX_NAME_BYTES),
+        }
+    }
+}
+
+fn has_extension(name: &str) -> bool {
+    Path::new(name).extension().is_some()
+}
+
+/// Checks a name typed by the user and returns it in its normalized form: trimmed, in Unicode NFC
+/// so the same name typed on different systems matches, and with `extension` added if it has none.
+pub fn validate_name(name: &str, extension: Option<&str>) -> Result<String, NameError> {
+    let mut name = name.trim().nfc().collect::<String>();
+    if name.is_empty() {
+        return Err(NameError::Empty);
+    }
+    if name.contains(['/', '\\']) {
+        return Err(NameError::PathSeparator);
+    }
+    if name.starts_with('.') {
+        return Err(NameError::LeadingDot);
+    }
+    if name.chars().any(|c| c.is_control()) {
+        return Err(NameError::ControlCharacter);
+    }
+
+    let stem = name.split('.').next().unwrap_or_default().trim().to_lowercase();
+    if RESERVED_NAMES.contains(&stem.as_str()) {
+        return Err(NameError::Reserved(name));
+    }
+
+    if let Some(extension) = extension.filter(|_| !has_extension(&name)) {
+        name = format!("{}.{}", name, extension);
+    }
+    if name.len() > MAX_NAME_BYTES {
+        return Err(NameError::TooLong(name.len()));
+    }
+    Ok(name)
+}
+
+/// A safe name close to `name`, e.g. `../My Trip: Day 1` becomes `My_Trip_Day_1.txt`
+pub fn suggest_name(name: &str, extension: Option<&str>) -> String {
+    let name = name.trim().nfc().collect::<String>();
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && ext.chars().all(|c| c.is_alphanumeric()) => (stem, Some(ext)),
+        _ => (name.as_str(), None),
+    };
+
+    let mut slug = String::new();
+    for c in stem.chars() {
+        if c.is_alphanumeric() || c == '-' {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('_') {
+            slug.push('_');
+        }
+    }
+    let mut slug = slug.trim_end_matches('_').to_string();
+    if slug.is_empty() {
+        slug = String::from("Untitled");
+    } else if RESERVED_NAMES.contains(&slug.to_lowercase().as_str()) {
+        slug.push_str("_entry");
+    }
+
+    let ext = ext.or(extension);
+    let limit = MAX_NAME_BYTES - ext.map(|e| e.len() + 1).unwrap_or(0);
+    while slug.len() > limit {
+        slug.pop();
+    }
+    match ext {
+        Some(ext) => format!("{}.{}", slug, ext),
+        None => slug,
+    }
+}
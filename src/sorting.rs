@@ -0,0 +1,167 @@
+use std::cmp::Ordering;
+use std::fs;
+use chrono::Local;
+use crate::database::EntryDB;
+use crate::file_operations::Entry;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Number,
+    Name,
+    Created,
+    Accessed,
+    Length,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Grouping {
+    None,
+    Month,
+    Tag,
+}
+
+pub const SORT_KEYS: [(SortKey, &str); 5] = [
+    (SortKey::Number, "Number"),
+    (SortKey::Name, "Name"),
+    (SortKey::Created, "Created"),
+    (SortKey::Accessed, "Last Accessed"),
+    (SortKey::Length, "Length"),
+];
+
+pub const GROUPINGS: [(Grouping, &str); 3] = [
+    (Grouping::None, "No Groups"),
+    (Grouping::Month, "Month"),
+    (Grouping::Tag, "Tag"),
+];
+
+/// How a list of entries is sorted and grouped, saved per list as e.g. `created desc month`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ListOrder {
+    pub key: SortKey,
+    pub descending: bool,
+    pub grouping: Grouping,
+}
+
+impl Default for ListOrder {
+    fn default() -> Self {
+        Self { key: SortKey::Number, descending: false, grouping: Grouping::None }
+    }
+}
+
+impl ListOrder {
+    fn parse(value: &str) -> Self {
+        let mut order = Self::default();
+        for word in value.split_whitespace() {
+            match word {
+                "number" => order.key = SortKey::Number,
+                "name" => order.key = SortKey::Name,
+                "created" => order.key = SortKey::Created,
+                "accessed" => order.key = SortKey::Accessed,
+                "length" => order.key = SortKey::Length,
+                "asc" => order.descending = false,
+                "desc" => order.descending = true,
+                "month" => order.grouping = Grouping::Month,
+                "tag" => order.grouping = Grouping::Tag,
+                _ => {}
+            }
+        }
+        order
+    }
+
+    fn render(&self) -> String {
+        let key = match self.key {
+            SortKey::Number => "number",
+            SortKey::Name => "name",
+            SortKey::Created => "created",
+            SortKey::Accessed => "accessed",
+            SortKey::Length => "length",
+        };
+        let grouping = match self.grouping {
+            Grouping::None => "",
+            Grouping::Month => " month",
+            Grouping::Tag => " tag",
+        };
+        format!("{} {}{}", key, if self.descending { "desc" } else { "asc" }, grouping)
+    }
+
+    /// The order last chosen for `list`, e.g. `edit` or `delete`
+    pub fn load(db: &EntryDB, list: &str) -> Self {
+        db.get_setting(&format!("order.{}", list)).map(|v| Self::parse(&v)).unwrap_or_default()
+    }
+
+    pub fn save(&self, db: &EntryDB, list: &str) {
+        db.set_setting(&format!("order.{}", list), &self.render());
+    }
+
+    pub fn sort(&self, entries: &mut [Entry]) {
+        // Entries without the date or number being sorted by go last either way
+        fn missing_last<T: Ord>(a: Option<T>, b: Option<T>, descending: bool) -> Ordering {
+            match (a, b) {
+                (Some(a), Some(b)) if descending => b.cmp(&a),
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        }
+        let length = |e: &Entry| fs::metadata(&e.path).map(|m| m.len()).unwrap_or(0);
+
+        match self.key {
+            SortKey::Number => entries.sort_by(|a, b| missing_last(a.number, b.number, self.descending)),
+            SortKey::Name => {
+                entries.sort_by_key(|e| e.name.to_lowercase());
+                if self.descending {
+                    entries.reverse();
+                }
+            },
+            SortKey::Created => entries.sort_by(|a, b| missing_last(a.entry_date, b.entry_date, self.descending)),
+            SortKey::Accessed => entries.sort_by(|a, b| missing_last(a.access_date, b.access_date, self.descending)),
+            SortKey::Length => {
+                // Cached so each file is only looked at once
+                entries.sort_by_cached_key(length);
+                if self.descending {
+                    entries.reverse();
+                }
+            },
+        }
+    }
+
+    /// Splits sorted entries into titled groups of indices into `entries`, keeping their order inside each group.
+    /// With tag grouping an entry is listed under each of its tags.
+    pub fn group(&self, db: &EntryDB, entries: &[Entry]) -> Vec<(String, Vec<usize>)> {
+        let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+        let mut add = |group: String, index: usize| match groups.iter_mut().find(|(g, _)| *g == group) {
+            Some((_, indices)) => indices.push(index),
+            None => groups.push((group, vec![index])),
+        };
+
+        match self.grouping {
+            Grouping::None => return vec![(String::new(), (0..entries.len()).collect())],
+            Grouping::Month => {
+                for (i, entry) in entries.iter().enumerate() {
+                    let month = entry.entry_date
+                        .map(|d| d.with_timezone(&Local).format("%B %Y").to_string())
+                        .unwrap_or_else(|| String::from("No Date"));
+                    add(month, i);
+                }
+            },
+            Grouping::Tag => {
+                let index = db.get_tag_index();
+                for (i, entry) in entries.iter().enumerate() {
+                    let tags = index.iter()
+                        .filter(|(_, names)| names.contains(&entry.name))
+                        .map(|(tag, _)| format!("#{}", tag))
+                        .collect::<Vec<String>>();
+                    if tags.is_empty() {
+                        add(String::from("Untagged"), i);
+                    }
+                    for tag in tags {
+                        add(tag, i);
+                    }
+                }
+                groups.sort_by_key(|(group, _)| (group == "Untagged", group.clone()));
+            },
+        }
+        groups
+    }
+}
@@ -0,0 +1,205 @@
+use std::fmt;
+use std::fs;
+use chrono::{DateTime, FixedOffset};
+use serde_json::Value;
+use crate::database::{EntryDB, EntryError};
+use crate::file_operations::{file_exists, from_db_date, get_entry, rewrite_entry_date, Entry};
+use crate::format::Format;
+use crate::front_matter::FrontMatter;
+use crate::{git, lock};
+
+/// A change recorded in the undo log
+#[derive(Debug)]
+pub struct Operation {
+    pub id: i64,
+    pub kind: String,
+    pub entry: String,
+    pub data: Value,
+    pub date: Option<DateTime<FixedOffset>>,
+    /// Shared by the operations of one batch, such as deleting several entries at once
+    pub batch: Option<i64>,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind.as_str() {
+            "create" => write!(f, "Create {}", self.entry),
+            "rename" => write!(f, "Rename {} to {}", self.data["from"].as_str().unwrap_or_default(), self.entry),
+            "metadata" => write!(f, "Edit metadata of {}", self.entry),
+            "title" => write!(f, "Retitle {}", self.entry),
+            "tags" => write!(f, "Retag {}", self.entry),
+            "delete" => write!(f, "Delete {}", self.entry),
+            kind => write!(f, "{} {}", kind, self.entry),
+        }
+    }
+}
+
+/// The operations making up the `count` most recent actions, newest first. A batch is one action.
+pub fn recent_operations(db: &EntryDB, count: usize) -> Vec<Operation> {
+    let mut stmt = db.conn.prepare("SELECT id, kind, entry, data, date, batch FROM operations ORDER BY id DESC")
+        .expect("Could not select operations in DB");
+    let operations = stmt.query_map([], |row| {
+        let data: String = row.get(3)?;
+        Ok(Operation {
+            id: row.get(0)?,
+            kind: row.get(1)?,
+            entry: row.get(2)?,
+            data: serde_json::from_str(&data).unwrap_or_default(),
+            date: from_db_date(&row.get::<_, String>(4)?, None),
+            batch: row.get(5)?,
+        })
+    }).expect("Error reading operations");
+
+    let mut actions = 0;
+    let mut recent: Vec<Operation> = Vec::new();
+    for operation in operations.filter_map(|o| o.ok()) {
+        let same_batch = operation.batch.is_some() && recent.last().is_some_and(|last| last.batch == operation.batch);
+        if !same_batch {
+            actions += 1;
+        }
+        if actions > count {
+            break;
+        }
+        recent.push(operation);
+    }
+    recent
+}
+
+fn find_entry(db: &EntryDB, name: &str) -> Result<Entry, String> {
+    get_entry(db.get_entries(), name).ok_or_else(|| format!("{} no longer exists", name))
+}
+
+fn text(data: &Value, key: &str) -> Option<String> {
+    data[key].as_str().map(String::from)
+}
+
+/// Puts back the row of a deleted entry whose file is already back in place: its number, dates,
+/// status, pin, open count and projects as saved in `data`. Nothing is changed unless all of it can be.
+fn restore_deleted(db: &EntryDB, name: &str, data: &Value) -> Result<Entry, EntryError> {
+    let mut entry = Entry::from_file(&db.config.entry_dir, name);
+    entry.number = data["number"].as_u64().map(|n| n as u32);
+    entry.entry_date = text(data, "entry_date").and_then(|d| from_db_date(&d, data["entry_offset"].as_i64().map(|o| o as i32)));
+    entry.access_date = text(data, "access_date").and_then(|d| from_db_date(&d, data["access_offset"].as_i64().map(|o| o as i32)));
+    entry.status = text(data, "status");
+    entry.pinned = data["pinned"].as_bool().unwrap_or(false);
+
+    let tx = db.conn.unchecked_transaction()?;
+    db.try_add_entry_to_db(&entry)?;
+    db.conn.execute(
+        "UPDATE entries SET pinned = ?1, open_count = ?2 WHERE name = ?3",
+        (entry.pinned, data["open_count"].as_u64().unwrap_or(0), &entry.name))?;
+    for project in data["projects"].as_array().into_iter().flatten() {
+        let project_name = project[0].as_str().unwrap_or_default();
+        let position = project[1].as_u64().map(|p| p as usize);
+        db.add_to_project(project_name, &entry, position)?;
+    }
+    tx.commit()?;
+    Ok(entry)
+}
+
+/// Reverses a metadata, title or tag change. Only what the operation changed is put back,
+/// so text written in the entry since is kept.
+fn restore_fields(db: &EntryDB, entry: &mut Entry, operation: &Operation) -> Result<(), EntryError> {
+    let data = &operation.data;
+    let before = FrontMatter::parse(data["content"].as_str().unwrap_or_default()).0.unwrap_or_default();
+    let format = Format::from_name(&entry.name);
+    let content = fs::read_to_string(&entry.path)?;
+
+    match operation.kind.as_str() {
+        "title" => fs::write(&entry.path, FrontMatter::set_value(&content, "title", before.title.as_deref(), format))?,
+        "tags" => fs::write(&entry.path, FrontMatter::set_tags(&content, &before.tags, format))?,
+        _ => {
+            let number = data["number"].as_u64().map(|n| n as u32);
+            let entry_date = text(data, "entry_date").and_then(|d| from_db_date(&d, data["entry_offset"].as_i64().map(|o| o as i32)));
+            if let Some(date) = entry_date.filter(|date| Some(*date) != entry.entry_date) {
+                rewrite_entry_date(&entry.path, &date, data["header"].as_bool().unwrap_or(false))?;
+            }
+            entry.number = number;
+            entry.entry_date = entry_date;
+            db.conn.execute(
+                "UPDATE entries SET number = ?1, entry_date = ?2, entry_offset = ?3 WHERE name = ?4",
+                (entry.number, entry.entry_string(), entry.entry_offset(), &entry.name))?;
+        },
+    }
+    db.index_entry(entry);
+    Ok(())
+}
+
+/// Reverses one operation, returning why it couldn't be if it can't
+fn reverse(db: &EntryDB, operation: &Operation) -> Result<(), String> {
+    let data = &operation.data;
+    match operation.kind.as_str() {
+        "create" => {
+            let mut entry = find_entry(db, &operation.entry)?;
+            // Undoing a create must not throw away what was written since
+            let content = fs::read_to_string(&entry.path).unwrap_or_default();
+            if content != data["content"].as_str().unwrap_or_default() {
+                return Err(format!("{} has been written in since it was created, delete it instead", entry.name));
+            }
+            db.delete_entry(&mut entry).map_err(|e| e.to_string())
+        },
+        "rename" => {
+            let mut entry = find_entry(db, &operation.entry)?;
+            let old_name = text(data, "from").ok_or("The log has no old name")?;
+            db.change_name(&mut entry, &old_name).map_err(|e| e.to_string())
+        },
+        "metadata" | "title" | "tags" => {
+            let mut entry = find_entry(db, &operation.entry)?;
+            if lock::entry_locked(&db.config, &entry.name) {
+                return Err(EntryError::Locked(entry.name).to_string());
+            }
+            let number = data["number"].as_u64().map(|n| n as u32);
+            if operation.kind == "metadata" && number.is_some() && db.get_entries().iter().any(|e| e.number == number && e.name != entry.name) {
+                return Err(EntryError::NumberTaken(number.unwrap_or_default()).to_string());
+            }
+            restore_fields(db, &mut entry, operation).map_err(|e| e.to_string())?;
+            git::commit(&db.config, &format!("Undo: {}", operation));
+            Ok(())
+        },
+        "delete" => {
+            let path = db.config.get_entry_path(&operation.entry);
+            if file_exists(&path) || get_entry(db.get_entries(), &operation.entry).is_some() {
+                return Err(EntryError::NameTaken(operation.entry.clone()).to_string());
+            }
+            let number = data["number"].as_u64().map(|n| n as u32);
+            let number_free = !db.get_entries().iter().any(|e| number.is_some() && e.number == number);
+
+            let mut data = data.clone();
+            if !number_free {
+                println!("Entry number {} is used by another entry now, {} was restored without one", number.unwrap_or_default(), operation.entry);
+                data["number"] = Value::Null;
+            }
+
+            fs::write(&path, data["content"].as_str().unwrap_or_default()).map_err(|e| e.to_string())?;
+            // Pinning isn't logged, so the pin is only put back along with a deleted entry.
+            // If the row can't be put back the file goes again, so the undo can be tried once more.
+            let mut entry = match restore_deleted(db, &operation.entry, &data) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    fs::remove_file(&path).ok();
+                    return Err(e.to_string());
+                },
+            };
+            db.index_entry(&mut entry);
+            git::commit(&db.config, &format!("Undo: {}", operation));
+            Ok(())
+        },
+        kind => Err(format!("Don't know how to undo {}", kind)),
+    }
+}
+
+/// Reverses the `count` most recent operations, newest first, stopping at the first that fails.
+/// Returns the operations undone.
+pub fn undo(db: &EntryDB, count: usize) -> Result<Vec<Operation>, (Vec<Operation>, String)> {
+    let mut undone = Vec::new();
+    for operation in recent_operations(db, count) {
+        if let Err(e) = reverse(db, &operation) {
+            return Err((undone, format!("Could not undo \"{}\": {}", operation, e)));
+        }
+        // Also drops what the reversal itself logged, e.g. the delete undoing a create
+        db.conn.execute("DELETE FROM operations WHERE id >= ?1", (operation.id,))
+            .expect("Could not update operation log");
+        undone.push(operation);
+    }
+    Ok(undone)
+}
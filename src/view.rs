@@ -0,0 +1,255 @@
+use console::{measure_text_width, Key, Style, Term};
+use regex::Regex;
+use crate::file_operations::{read_entry_body, Entry};
+use crate::format::{convert_headings, Format};
+
+/// Lines are wrapped at the terminal width, but never wider than this so paragraphs stay readable
+const MAX_WIDTH: usize = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Inline {
+    bold: bool,
+    italic: bool,
+    code: bool,
+    link: bool,
+}
+
+impl Inline {
+    fn style(&self) -> Style {
+        let mut style = Style::new();
+        if self.bold {
+            style = style.bold();
+        }
+        if self.italic {
+            style = style.italic();
+        }
+        if self.code {
+            style = style.cyan();
+        }
+        if self.link {
+            style = style.underlined().blue();
+        }
+        style
+    }
+}
+
+/// A word made of differently styled pieces, e.g. `**bold**,`
+type Word = Vec<(String, Inline)>;
+
+fn word_width(word: &Word) -> usize {
+    word.iter().map(|(text, _)| measure_text_width(text)).sum()
+}
+
+fn render_word(word: &Word, base: Style) -> String {
+    word.iter().map(|(text, inline)| {
+        let style = if *inline == Inline::default() { base.clone() } else { inline.style() };
+        style.apply_to(text).to_string()
+    }).collect()
+}
+
+/// Splits a line of Markdown into words, tracking `**bold**`, `*italic*`, `` `code` `` and links
+fn parse_inline(text: &str) -> Vec<Word> {
+    let links = Regex::new(r"\[\[([^\]|]+)(?:\|([^\]]*))?\]\]|\[([^\]]+)\]\([^)]*\)").unwrap();
+    let text = links.replace_all(text, |c: &regex::Captures| {
+        let label = c.get(2).or(c.get(1)).or(c.get(3)).map(|m| m.as_str()).unwrap_or_default();
+        // Marked with control characters so the label itself is still parsed for emphasis
+        format!("\u{1}{}\u{2}", label)
+    });
+
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut words: Vec<Word> = Vec::new();
+    let mut word: Word = Vec::new();
+    let mut piece = String::new();
+    let mut inline = Inline::default();
+    let mut i = 0;
+
+    let flush = |piece: &mut String, word: &mut Word, inline: Inline| {
+        if !piece.is_empty() {
+            word.push((std::mem::take(piece), inline));
+        }
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let prev = if i == 0 { None } else { chars.get(i - 1).copied() };
+        let toggle = match c {
+            '`' => Some("code"),
+            _ if inline.code => None,
+            '*' if next == Some('*') => Some("bold"),
+            '*' => Some("italic"),
+            // Only at word edges, so snake_case names stay as they are
+            '_' if prev.is_none_or(|p| !p.is_alphanumeric()) || next.is_none_or(|n| !n.is_alphanumeric()) => Some("italic"),
+            '\u{1}' | '\u{2}' => Some("link"),
+            _ => None,
+        };
+
+        match toggle {
+            Some(kind) => {
+                flush(&mut piece, &mut word, inline);
+                match kind {
+                    "code" => inline.code = !inline.code,
+                    "bold" => {
+                        inline.bold = !inline.bold;
+                        i += 1;
+                    },
+                    "italic" => inline.italic = !inline.italic,
+                    _ => inline.link = c == '\u{1}',
+                }
+            },
+            None if c.is_whitespace() => {
+                flush(&mut piece, &mut word, inline);
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            },
+            None => piece.push(c),
+        }
+        i += 1;
+    }
+    flush(&mut piece, &mut word, inline);
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Word-wraps `text` to `width`, starting each line with `first` and following lines with `rest`
+fn wrap(text: &str, width: usize, first: &str, rest: &str, base: Style) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = first.to_string();
+    let mut used = measure_text_width(first);
+    let mut empty = true;
+
+    for word in parse_inline(text) {
+        let length = word_width(&word);
+        if !empty && used + 1 + length > width {
+            lines.push(std::mem::replace(&mut line, rest.to_string()));
+            used = measure_text_width(rest);
+            empty = true;
+        }
+        if !empty {
+            line.push(' ');
+            used += 1;
+        }
+        line.push_str(&render_word(&word, base.clone()));
+        used += length;
+        empty = false;
+    }
+    lines.push(line);
+    lines
+}
+
+/// Renders Markdown for the terminal: headings, emphasis, lists, quotes and code
+pub fn render_markdown(text: &str, width: usize) -> Vec<String> {
+    let heading = Regex::new(r"^(#{1,6})\s+(.*)$").unwrap();
+    let list = Regex::new(r"^(\s*)([-*+]|\d+[.)])\s+(.*)$").unwrap();
+    let rule = Regex::new(r"^\s*([-*_])(\s*([-*_])){2,}\s*$").unwrap();
+    let code = Style::new().cyan();
+    let dim = Style::new().dim();
+
+    let mut lines = Vec::new();
+    let mut in_code = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            lines.push(format!("    {}", code.apply_to(line)));
+            continue;
+        }
+
+        if let Some(c) = heading.captures(line) {
+            let style = match c[1].len() {
+                1 => Style::new().bold().underlined().magenta(),
+                2 => Style::new().bold().magenta(),
+                _ => Style::new().bold(),
+            };
+            lines.extend(wrap(&c[2], width, "", "", style));
+        } else if rule.is_match(line) {
+            lines.push(dim.apply_to("─".repeat(width)).to_string());
+        } else if let Some(c) = list.captures(line) {
+            let marker = match &c[2] {
+                "-" | "*" | "+" => "•".to_string(),
+                number => number.to_string(),
+            };
+            let first = format!("{}{} ", &c[1], marker);
+            let rest = " ".repeat(measure_text_width(&first));
+            lines.extend(wrap(&c[3], width, &first, &rest, Style::new()));
+        } else if let Some(quote) = line.trim_start().strip_prefix('>') {
+            let bar = dim.apply_to("│ ").to_string();
+            lines.extend(wrap(quote.trim(), width, &bar, &bar, Style::new().italic()));
+        } else if line.trim().is_empty() {
+            lines.push(String::new());
+        } else {
+            lines.extend(wrap(line.trim(), width, "", "", Style::new()));
+        }
+    }
+    lines
+}
+
+/// An entry's title, date and text, rendered for the terminal
+pub fn render_entry(entry: &Entry, width: usize) -> Vec<String> {
+    let body = read_entry_body(&entry.path);
+    let body = convert_headings(&body, Format::from_name(&entry.name), Format::Markdown, 0);
+
+    let mut lines = wrap(&entry.display_title(), width, "", "", Style::new().bold().underlined());
+    if let Some(date) = entry.entry_date {
+        lines.push(Style::new().dim().apply_to(date.format("%A %-d %B %Y, %H:%M")).to_string());
+    }
+    lines.push(String::new());
+    lines.extend(render_markdown(&body, width));
+    lines
+}
+
+/// Shows `lines` a screen at a time. Without a terminal they are printed as they are.
+fn page(term: &Term, name: &str, lines: &[String]) {
+    let rows = term.size().0.max(2) as usize - 1;
+    let last = lines.len().saturating_sub(rows);
+    let mut top = 0;
+
+    // Alternate screen, so the pager leaves the terminal as it found it
+    term.write_str("\x1b[?1049h").ok();
+    term.hide_cursor().ok();
+    loop {
+        term.clear_screen().ok();
+        for line in lines.iter().skip(top).take(rows) {
+            term.write_line(line).ok();
+        }
+        for _ in lines.len().saturating_sub(top)..rows {
+            term.write_line("~").ok();
+        }
+        let status = format!(
+            "{}  {}-{}/{}  j/k scroll, space/b page, g/G top/end, q quit",
+            name, top + 1, (top + rows).min(lines.len()), lines.len());
+        term.write_str(&Style::new().reverse().apply_to(status).to_string()).ok();
+
+        top = match term.read_key() {
+            Ok(Key::ArrowDown) | Ok(Key::Enter) | Ok(Key::Char('j')) => top + 1,
+            Ok(Key::ArrowUp) | Ok(Key::Char('k')) => top.saturating_sub(1),
+            Ok(Key::PageDown) | Ok(Key::Char(' ')) | Ok(Key::Char('f')) => top + rows,
+            Ok(Key::PageUp) | Ok(Key::Char('b')) => top.saturating_sub(rows),
+            Ok(Key::Home) | Ok(Key::Char('g')) => 0,
+            Ok(Key::End) | Ok(Key::Char('G')) => last,
+            Ok(Key::Char('q')) | Ok(Key::Escape) | Ok(Key::CtrlC) | Err(_) => break,
+            _ => top,
+        }.min(last);
+    }
+    term.show_cursor().ok();
+    term.write_str("\x1b[?1049l").ok();
+}
+
+/// Shows an entry read-only, without opening the editor or touching its access date
+pub fn view_entry(entry: &Entry) {
+    let term = Term::stdout();
+    if !term.is_term() {
+        for line in render_entry(entry, MAX_WIDTH) {
+            println!("{}", line);
+        }
+        return;
+    }
+
+    let width = (term.size().1 as usize).saturating_sub(1).clamp(20, MAX_WIDTH);
+    page(&term, &entry.name, &render_entry(entry, width));
+}
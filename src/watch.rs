@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+use inotify::{EventMask, Inotify, WatchMask};
+use crate::check::{self, Problem};
+use crate::database::EntryDB;
+use crate::file_operations::{file_exists, get_entry};
+use crate::{git, lock};
+
+/// Editor swap files, backups and the journal's own temporary files
+fn ignored(name: &str) -> bool {
+    name.starts_with('.') || name.ends_with('~') || name.ends_with(".swp") || name.ends_with(".tmp")
+}
+
+/// Brings an entry's row up to date with its file, adding a row for new files
+fn sync_file(db: &EntryDB, name: &str) {
+    if !file_exists(&db.config.get_entry_path(name)) {
+        return;
+    }
+    match get_entry(db.get_entries(), name) {
+        Some(mut entry) => {
+            db.index_entry(&mut entry);
+            println!("Updated {}", name);
+        },
+        None => {
+            db.sync_untracked_file(name);
+            println!("Added {}", name);
+        },
+    }
+}
+
+fn forget_file(db: &EntryDB, name: &str) {
+    // Written again since, e.g. by an editor that moves the old file aside first
+    if file_exists(&db.config.get_entry_path(name)) {
+        return;
+    }
+    match db.forget_entry(name) {
+        Ok(true) => println!("Removed {}", name),
+        Ok(false) => {},
+        Err(e) => println!("Could not remove {}: {}", name, e),
+    }
+}
+
+fn rename_file(db: &EntryDB, old_name: &str, new_name: &str) {
+    match db.entry_renamed(old_name, new_name) {
+        Ok(true) => println!("Renamed {} to {}", old_name, new_name),
+        // Renamed by the journal itself, or the old name was never tracked
+        Ok(false) => sync_file(db, new_name),
+        Err(e) => println!("Could not rename {} to {}: {}", old_name, new_name, e),
+    }
+}
+
+/// Adds rows for files created while the watcher wasn't running, and drops rows whose file is gone
+fn initial_sync(db: &EntryDB) {
+    let problems = check::check(db).into_iter()
+        .filter(|p| matches!(p, Problem::MissingFile(_) | Problem::UntrackedFile(_)))
+        .collect::<Vec<Problem>>();
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+    if check::repair(db, &problems, &["missing-files", "untracked-files"]) > 0 {
+        git::commit(&db.config, "Sync entries from disk");
+    }
+}
+
+fn add_watch(inotify: &mut Inotify, db: &EntryDB) {
+    inotify.watches().add(
+        &db.config.entry_dir,
+        WatchMask::CLOSE_WRITE | WatchMask::MOVED_FROM | WatchMask::MOVED_TO | WatchMask::DELETE,
+    ).expect("Could not watch entry directory");
+}
+
+/// Watches the entry directory and keeps the database in step with files created, changed,
+/// renamed or deleted by other programs. Runs until the process is stopped.
+pub fn watch(db: &EntryDB) {
+    let mut inotify = Inotify::init().expect("Could not start inotify");
+    add_watch(&mut inotify, db);
+    initial_sync(db);
+    println!("Watching {}", db.config.entry_dir);
+
+    let mut buffer = [0; 4096];
+    loop {
+        let events = inotify.read_events_blocking(&mut buffer)
+            .expect("Could not read file events")
+            .map(|e| (e.mask, e.cookie, e.name.map(|n| n.to_string_lossy().to_string())))
+            .collect::<Vec<_>>();
+
+        // A restore replaces the entry directory, which removes the watch
+        if events.iter().any(|(mask, _, _)| mask.contains(EventMask::IGNORED)) {
+            while lock::journal_locked(&db.config) || !file_exists(&db.config.entry_dir) {
+                thread::sleep(Duration::from_secs(1));
+            }
+            add_watch(&mut inotify, db);
+            initial_sync(db);
+            continue;
+        }
+        // Changes seen while another session holds the journal lock are applied once it is released.
+        // Later events wait in inotify's queue meanwhile.
+        while lock::journal_locked(&db.config) {
+            thread::sleep(Duration::from_secs(1));
+        }
+
+        // A rename arrives as MOVED_FROM and MOVED_TO events sharing a cookie
+        let mut moved_from: HashMap<u32, String> = HashMap::new();
+        let mut changed = false;
+        for (mask, cookie, name) in events {
+            let name = match name {
+                Some(name) => name,
+                None => continue,
+            };
+
+            if mask.contains(EventMask::MOVED_FROM) {
+                if !ignored(&name) {
+                    moved_from.insert(cookie, name);
+                }
+                continue;
+            }
+            // Paired up even when moved to an ignored name, like vim's `Entry_1.txt~` backup,
+            // so the entry isn't forgotten as if it had left the directory
+            let old_name = match mask.contains(EventMask::MOVED_TO) {
+                true => moved_from.remove(&cookie),
+                false => None,
+            };
+            if ignored(&name) {
+                continue;
+            }
+
+            if mask.contains(EventMask::MOVED_TO) {
+                match old_name {
+                    Some(old_name) => rename_file(db, &old_name, &name),
+                    None => sync_file(db, &name),
+                }
+            } else if mask.contains(EventMask::DELETE) {
+                forget_file(db, &name);
+            } else {
+                sync_file(db, &name);
+            }
+            changed = true;
+        }
+
+        // Moved out of the entry directory
+        for name in moved_from.values() {
+            forget_file(db, name);
+            changed = true;
+        }
+        if changed {
+            git::commit(&db.config, "Sync entries from disk");
+        }
+    }
+}